@@ -55,6 +55,11 @@ wasm.__wbindgen_start()
     'export function $1('
   )
 
+  wasmJs = wasmJs.replace(
+    /^(?:module\.)?exports\.(\w+)\s*=\s*\1;?$/gm,
+    'export { $1 }'
+  )
+
   wasmJs = wasmJs.replace(/wasm\.__wbindgen_start\(\);?\s*$/, '')
 
   const finalCode = header + wasmJs + initCode
@@ -1,9 +1,11 @@
-import { Schema } from 'koishi'
+import { type Dict, Schema } from 'koishi'
 
 export interface Config {
   timeout: number
   zoom: number
   cover: boolean
+  template: string
+  effects: Dict<string, string>
   quality:
     | 'fine'
     | 'normal'
@@ -12,17 +14,52 @@ export interface Config {
     | 'extraCoarse'
     | 'emboss'
     | 'sketch'
+  kernelShape: 'box' | 'gaussian' | 'disk'
+  lineExtractor: 'highpass' | 'dog' | 'xdog' | 'canny'
   denoise: boolean
+  denoiseMode: 'gaussian' | 'median' | 'bilateral' | 'guided' | 'nlmeans'
+  denoiseStrength: number
   lightCut: number
   darkCut: number
+  autoLevels: boolean
+  autoCut: boolean
+  inkCoverage: number
+  contrast: 'none' | 'clahe' | 'local'
+  lineWidth: number
   shade: boolean
   shadeLimit: number
   shadeLight: number
   toneCount: number
   light: number
+  gamma: number
+  antialias: 'blur' | 'smaa' | 'fxaa' | 'ssaa' | 'none'
+  sharpen: boolean
+  sharpenAmount: number
+  sharpenRadius: number
+  sharpenThreshold: number
   kiss: boolean
   watermark: boolean
   hajimei: boolean
+  watermarkText: string
+  watermarkFont: string
+  watermarkPosition:
+    | 'top-left'
+    | 'top'
+    | 'top-right'
+    | 'left'
+    | 'center'
+    | 'right'
+    | 'bottom-left'
+    | 'bottom'
+    | 'bottom-right'
+  watermarkScale: number
+  watermarkOpacity: number
+  watermarkRotation: number
+  watermarkMode: 'single' | 'tile' | 'tile-only'
+  watermarkTileAngle: number
+  watermarkTileSpacing: number
+  watermarkTileOpacity: number
+  markId: string
 }
 
 export const Config: Schema<Config> = Schema.intersect([
@@ -42,7 +79,16 @@ export const Config: Schema<Config> = Schema.intersect([
       .max(4)
       .step(0.1),
 
-    cover: Schema.boolean().default(false).description('是否裁剪为正方形')
+    cover: Schema.boolean().default(false).description('是否裁剪为正方形'),
+    template: Schema.string()
+      .role('textarea')
+      .default('')
+      .description('海报模板（JSON，格式见 README），留空输出原图大小的线稿'),
+    effects: Schema.dict(Schema.string().role('textarea'))
+      .default({})
+      .description(
+        '自定义效果：名称 → 流水线（JSON 数组或 TOML，格式见 README），使用 oli -e <名称> 调用'
+      )
   }).description('基础设置'),
 
   // 质量设置（模式）
@@ -59,7 +105,41 @@ export const Config: Schema<Config> = Schema.intersect([
       .default('normal')
       .description('线稿质量 / 模式（7种模式）'),
 
-    denoise: Schema.boolean().default(true).description('是否启用降噪')
+    kernelShape: Schema.union([
+      Schema.const('box').description('均值'),
+      Schema.const('gaussian').description('高斯'),
+      Schema.const('disk').description('圆盘')
+    ])
+      .default('box')
+      .description('线稿卷积核形状'),
+
+    lineExtractor: Schema.union([
+      Schema.const('highpass').description('高通滤波'),
+      Schema.const('dog').description('高斯差分'),
+      Schema.const('xdog').description('XDoG 软阈值'),
+      Schema.const('canny').description('Canny 边缘检测')
+    ])
+      .default('highpass')
+      .description('线稿提取算法'),
+
+    denoise: Schema.boolean().default(true).description('是否启用降噪'),
+
+    denoiseMode: Schema.union([
+      Schema.const('gaussian').description('高斯'),
+      Schema.const('median').description('中值'),
+      Schema.const('bilateral').description('双边'),
+      Schema.const('guided').description('引导滤波'),
+      Schema.const('nlmeans').description('非局部均值')
+    ])
+      .default('gaussian')
+      .description('降噪算法'),
+
+    denoiseStrength: Schema.number()
+      .default(1)
+      .description('降噪强度')
+      .min(0)
+      .max(5)
+      .step(0.1)
   }).description('质量设置'),
 
   // 线迹设置
@@ -76,7 +156,37 @@ export const Config: Schema<Config> = Schema.intersect([
       .description('线迹轻重 - 深色截断值')
       .min(0)
       .max(255)
-      .step(1)
+      .step(1),
+
+    autoLevels: Schema.boolean()
+      .default(false)
+      .description('根据图片自动选择截断值（忽略上面两项）'),
+
+    autoCut: Schema.boolean()
+      .default(false)
+      .description('按目标墨迹覆盖率自动选择截断值，并在结果后附上选出的值'),
+
+    inkCoverage: Schema.number()
+      .default(10)
+      .description('目标墨迹覆盖率（%）')
+      .min(1)
+      .max(60)
+      .step(1),
+
+    contrast: Schema.union([
+      Schema.const('none').description('不处理'),
+      Schema.const('clahe').description('自适应直方图均衡（CLAHE）'),
+      Schema.const('local').description('局部直方图均衡')
+    ])
+      .default('none')
+      .description('局部对比度增强（适合逆光或昏暗的照片）'),
+
+    lineWidth: Schema.number()
+      .default(0)
+      .description('线条粗细调整（像素，正数加粗、负数变细）')
+      .min(-5)
+      .max(5)
+      .step(0.5)
   }).description('线迹设置'),
 
   // 调子设置
@@ -109,9 +219,52 @@ export const Config: Schema<Config> = Schema.intersect([
       .description('额外亮度调整（百分比）')
       .min(-100)
       .max(100)
-      .step(1)
+      .step(1),
+
+    gamma: Schema.number()
+      .default(1)
+      .description('提取线稿前的 gamma 调整（大于 1 提亮暗部）')
+      .min(0.2)
+      .max(5)
+      .step(0.1)
   }).description('调子设置'),
 
+  // 后处理设置
+  Schema.object({
+    antialias: Schema.union([
+      Schema.const('blur').description('边缘模糊'),
+      Schema.const('smaa').description('SMAA 形态学抗锯齿'),
+      Schema.const('fxaa').description('FXAA 快速抗锯齿'),
+      Schema.const('ssaa').description('2 倍超采样'),
+      Schema.const('none').description('关闭')
+    ])
+      .default('blur')
+      .description('抗锯齿算法'),
+
+    sharpen: Schema.boolean().default(true).description('是否启用锐化'),
+
+    sharpenAmount: Schema.number()
+      .default(1)
+      .description('锐化强度')
+      .min(0)
+      .max(5)
+      .step(0.1),
+
+    sharpenRadius: Schema.number()
+      .default(0.9)
+      .description('锐化半径')
+      .min(0.1)
+      .max(5)
+      .step(0.1),
+
+    sharpenThreshold: Schema.number()
+      .default(0)
+      .description('锐化阈值（低于该对比度的像素不锐化）')
+      .min(0)
+      .max(255)
+      .step(1)
+  }).description('后处理设置'),
+
   // Kiss 效果
   Schema.object({
     kiss: Schema.boolean()
@@ -123,7 +276,89 @@ export const Config: Schema<Config> = Schema.intersect([
   Schema.object({
     watermark: Schema.boolean().default(true).description('是否添加水印'),
 
-    hajimei: Schema.boolean().default(false).description('是否使用初回样式水印')
+    hajimei: Schema.boolean().default(false).description('是否使用初回样式水印'),
+
+    watermarkText: Schema.string()
+      .default('')
+      .description(
+        '文字水印（留空使用图片水印），可用 {user} 表示发送者、{date} 表示日期；内置字体只含西文字符'
+      ),
+
+    watermarkFont: Schema.string()
+      .default('')
+      .description(
+        '文字水印字体文件路径（TTF/OTF），留空使用内置字体；发送者昵称含中文时需要设置含中文的字体'
+      ),
+
+    watermarkPosition: Schema.union([
+      Schema.const('top-left').description('左上'),
+      Schema.const('top').description('上'),
+      Schema.const('top-right').description('右上'),
+      Schema.const('left').description('左'),
+      Schema.const('center').description('居中'),
+      Schema.const('right').description('右'),
+      Schema.const('bottom-left').description('左下'),
+      Schema.const('bottom').description('下'),
+      Schema.const('bottom-right').description('右下')
+    ])
+      .default('bottom-right')
+      .description('水印位置'),
+
+    watermarkScale: Schema.number()
+      .default(1)
+      .description('水印大小倍数')
+      .min(0.1)
+      .max(5)
+      .step(0.1),
+
+    watermarkOpacity: Schema.number()
+      .default(1)
+      .description('水印不透明度')
+      .min(0)
+      .max(1)
+      .step(0.05),
+
+    watermarkRotation: Schema.number()
+      .default(0)
+      .description('水印旋转角度（顺时针）')
+      .min(-180)
+      .max(180)
+      .step(1),
+
+    watermarkMode: Schema.union([
+      Schema.const('single').description('单个水印'),
+      Schema.const('tile').description('平铺并保留单个水印'),
+      Schema.const('tile-only').description('只平铺')
+    ])
+      .default('single')
+      .description('水印绘制方式（平铺可防止二次转载）'),
+
+    watermarkTileAngle: Schema.number()
+      .default(-30)
+      .description('平铺水印倾斜角度（顺时针）')
+      .min(-180)
+      .max(180)
+      .step(1),
+
+    watermarkTileSpacing: Schema.number()
+      .default(1)
+      .description('平铺水印间距（水印高度的倍数）')
+      .min(0)
+      .max(5)
+      .step(0.1),
+
+    watermarkTileOpacity: Schema.number()
+      .default(0.15)
+      .description('平铺水印不透明度')
+      .min(0)
+      .max(1)
+      .step(0.05),
+
+    markId: Schema.string()
+      .default('')
+      .description(
+        '不可见来源标记 ID（最多 13 字节，留空不写入），转发的图片可用 oli.mark 读取'
+      )
   }).description('水印设置')
 ])
 
@@ -1,19 +1,60 @@
+import { readFileSync } from 'node:fs'
 import { type Context, h, type Logger } from 'koishi'
 import type Config from './config'
-import { processOneLastImage } from './utils/imageProcessing'
-// inlined watermark asset (data:<mime>;base64,...) provided by rolldown
-//@ts-expect-error
-import logoDataUri from './assets/one-last-image-logo2.png'
+import {
+  detectMark,
+  pipelineStages,
+  probeImage,
+  processContactSheet,
+  processOneLastImage,
+  processOneLastImageWithInfo
+} from './utils/imageProcessing'
+// inlined asset (data:<mime>;base64,...) provided by rolldown
+// 水印使用 rslib 内置的官方水印，不再需要传入
 //@ts-expect-error
 import pencilTextureUri from './assets/pencil-texture.jpg'
 
 export let logger: Logger
 
 export function apply(ctx: Context, config: Config) {
+  // 文字水印字体：启动时读取一次，转为 rslib 需要的 base64
+  let watermarkFont: string | undefined
+  if (config.watermarkFont) {
+    try {
+      watermarkFont = readFileSync(config.watermarkFont).toString('base64')
+    } catch (error) {
+      logger?.warn?.('文字水印字体无法读取，已使用内置字体', { error })
+    }
+  }
+
   ctx
     .command('oli', 'One Last Image 图片处理')
     .option('watermark', '-w [enable:boolean] 是否添加水印（默认使用配置）')
+    .option('effect', '-e <name:string> 使用自定义效果')
+    .option('debug', '-d 发送各处理阶段中间结果的拼图')
     .action(async ({ session, options }) => {
+      // 自定义效果：JSON 数组直接作为阶段列表，否则按 TOML 交给 rslib 解析
+      let pipeline: unknown
+      if (options.effect !== undefined) {
+        const source = config.effects[options.effect]
+        if (source === undefined) {
+          const names = Object.keys(config.effects)
+          return names.length > 0
+            ? `没有名为 ${options.effect} 的效果，可用的效果：${names.join('、')}`
+            : '没有配置自定义效果'
+        }
+        try {
+          pipeline = JSON.parse(source)
+        } catch {
+          pipeline = source
+        }
+        try {
+          pipelineStages({ pipeline })
+        } catch (error) {
+          return `效果 ${options.effect} 无效：${error instanceof Error ? error.message : error}`
+        }
+      }
+
       let [img] = h.select(session.elements, 'img')
 
       if (!img && session.quote) {
@@ -79,6 +120,10 @@ export function apply(ctx: Context, config: Config) {
           })
         )
 
+        if (!probeImage(imageBuffer)) {
+          return '不支持的图片格式或图片过大'
+        }
+
         // 水印配置：优先使用命令选项，否则使用配置文件
         const useWatermark =
           options.watermark !== undefined ? options.watermark : config.watermark
@@ -87,21 +132,53 @@ export function apply(ctx: Context, config: Config) {
           zoom: config.zoom,
           cover: config.cover,
           quality: config.quality,
+          kernel_shape: config.kernelShape,
+          line_extractor: config.lineExtractor,
           denoise: config.denoise,
+          denoise_mode: config.denoiseMode,
+          denoise_strength: config.denoiseStrength,
           light_cut: config.lightCut,
           dark_cut: config.darkCut,
+          auto_levels: config.autoLevels,
+          auto_cut: config.autoCut,
+          ink_coverage: config.inkCoverage,
+          contrast: config.contrast,
+          line_width: config.lineWidth,
           shade: config.shade,
           shade_limit: config.shadeLimit,
           shade_light: config.shadeLight,
+          curve: config.gamma !== 1 ? { gamma: config.gamma } : undefined,
+          antialias: config.antialias,
+          sharpen: config.sharpen,
+          sharpen_amount: config.sharpenAmount,
+          sharpen_radius: config.sharpenRadius,
+          sharpen_threshold: config.sharpenThreshold,
           kiss: config.kiss,
           watermark: useWatermark,
-          hajimei: config.hajimei
+          hajimei: config.hajimei,
+          watermark_text: config.watermarkText
+            ? config.watermarkText
+                .replaceAll('{user}', session.username || session.userId)
+                .replaceAll('{date}', new Date().toLocaleDateString('sv-SE'))
+            : undefined,
+          watermark_font: watermarkFont,
+          watermark_position: config.watermarkPosition,
+          watermark_scale: config.watermarkScale,
+          watermark_opacity: config.watermarkOpacity,
+          watermark_rotation: config.watermarkRotation,
+          watermark_mode: config.watermarkMode,
+          watermark_tile_angle: config.watermarkTileAngle,
+          watermark_tile_spacing: config.watermarkTileSpacing,
+          watermark_tile_opacity: config.watermarkTileOpacity,
+          mark_id: config.markId || undefined,
+          pipeline
         }
 
-        if (useWatermark && typeof logoDataUri === 'string') {
-          const parts = logoDataUri.split(',')
-          if (parts.length === 2) {
-            cfg.watermark_image = parts[1]
+        if (config.template) {
+          try {
+            cfg.template = JSON.parse(config.template)
+          } catch (error) {
+            logger.warn('海报模板不是有效的 JSON，已忽略', { error })
           }
         }
 
@@ -112,6 +189,25 @@ export function apply(ctx: Context, config: Config) {
           }
         }
 
+        if (options.debug) {
+          const sheet = await processContactSheet(imageBuffer, cfg)
+          return sheet ? h.image(sheet, 'image/png') : '图片处理失败了喵~'
+        }
+
+        if (config.autoCut) {
+          const { image, info } = await processOneLastImageWithInfo(
+            imageBuffer,
+            cfg
+          )
+          await session.send(h.image(image, 'image/png'))
+          if (info?.light_cut != null && info?.dark_cut != null) {
+            await session.send(
+              `浅色截断值 ${info.light_cut}，深色截断值 ${info.dark_cut}，墨迹覆盖率 ${info.ink_coverage.toFixed(1)}%`
+            )
+          }
+          return
+        }
+
         const processedBuffer = await processOneLastImage(imageBuffer, cfg)
 
         await session.send(h.image(processedBuffer, 'image/png'))
@@ -122,6 +218,31 @@ export function apply(ctx: Context, config: Config) {
         return '图片处理失败了喵~'
       }
     })
+
+  ctx
+    .command('oli.mark', '读取图片中的不可见来源标记')
+    .action(async ({ session }) => {
+      const [img] = [
+        ...h.select(session.elements, 'img'),
+        ...(session.quote ? h.select(session.quote.content, 'img') : [])
+      ]
+      if (!img) {
+        return '请发送或引用要检查的图片'
+      }
+
+      try {
+        const imageBuffer = Buffer.from(
+          await ctx.http.get(img.attrs.src, {
+            responseType: 'arraybuffer'
+          })
+        )
+        const id = detectMark(imageBuffer)
+        return id !== undefined ? `来源标记：${id}` : '未检测到来源标记'
+      } catch (error) {
+        logger.error('读取来源标记时发生错误', { error })
+        return '图片读取失败了喵~'
+      }
+    })
 }
 
 export * from './config'
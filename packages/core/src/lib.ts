@@ -3,7 +3,40 @@
  * 可以独立于 Koishi 使用
  */
 
-import { one_last_image as one_last_image_rslib } from './wasm/bindings'
+import {
+  detect_mark as detect_mark_rslib,
+  one_last_image as one_last_image_rslib,
+  one_last_image_debug as one_last_image_debug_rslib,
+  one_last_image_with_info as one_last_image_with_info_rslib,
+  pipeline_stages as pipeline_stages_rslib,
+  probe as probe_rslib
+} from './wasm/bindings'
+
+/**
+ * 图片元信息
+ */
+export interface ImageInfo {
+  format: string
+  width: number
+  height: number
+  frame_count: number
+  color_type: string
+  bit_depth: number
+  /** EXIF 方向（1-8，1 表示无旋转） */
+  orientation: number
+  has_alpha: boolean
+}
+
+/**
+ * 处理时实际使用的参数
+ */
+export interface RenderInfo {
+  /** 未做截断（sketch、xdog、canny）时为 null */
+  light_cut: number | null
+  dark_cut: number | null
+  /** 输出中暗于 128 的像素百分比 */
+  ink_coverage: number
+}
 
 /**
  * 将图片转换为线稿效果
@@ -32,3 +65,135 @@ export function one_last_image(
     return Buffer.isBuffer(imageBuffer) ? imageBuffer : Buffer.from(imageBuffer)
   }
 }
+
+/**
+ * 将图片转换为线稿效果，同时返回实际使用的截断值与墨迹覆盖率
+ * （auto_cut / auto_levels 选出的值可以填回 light_cut / dark_cut 复用）
+ * @param imageBuffer 图片的 Buffer 或 Uint8Array
+ * @param config 可选配置对象
+ * @returns 处理后的图片 Buffer 与参数，处理失败时抛出异常
+ */
+export function one_last_image_with_info(
+  imageBuffer: Buffer | Uint8Array,
+  config?: Record<string, any>
+): { image: Buffer; info: RenderInfo } {
+  const input =
+    imageBuffer instanceof Buffer ? new Uint8Array(imageBuffer) : imageBuffer
+  const configJson =
+    config && Object.keys(config).length > 0
+      ? JSON.stringify(config)
+      : undefined
+  const output = one_last_image_with_info_rslib(input, configJson)
+  try {
+    return {
+      image: Buffer.from(output.image),
+      info: JSON.parse(output.info)
+    }
+  } finally {
+    output.free()
+  }
+}
+
+/**
+ * 以调试模式处理图片，返回每个阶段执行后的中间结果
+ * （灰度、降噪、线稿、截断、抗锯齿的边缘图与混合权重、锐化、着色等，名称与阶段名称相同）
+ * @param imageBuffer 图片的 Buffer 或 Uint8Array
+ * @param config 可选配置对象
+ * @returns 按执行顺序排列的名称与 PNG 图片，处理失败时抛出异常
+ */
+export function one_last_image_debug(
+  imageBuffer: Buffer | Uint8Array,
+  config?: Record<string, any>
+): { name: string; image: Buffer }[] {
+  const input =
+    imageBuffer instanceof Buffer ? new Uint8Array(imageBuffer) : imageBuffer
+  const configJson =
+    config && Object.keys(config).length > 0
+      ? JSON.stringify(config)
+      : undefined
+  const output = one_last_image_debug_rslib(input, configJson)
+  try {
+    return output.names.map((name, index) => ({
+      name,
+      image: Buffer.from(output.image(index) ?? [])
+    }))
+  } finally {
+    output.free()
+  }
+}
+
+/**
+ * 以调试模式处理图片，把每个阶段的中间结果拼成一张带名称标注的图片
+ * @param imageBuffer 图片的 Buffer 或 Uint8Array
+ * @param config 可选配置对象
+ * @returns 拼图的 PNG Buffer，处理失败时抛出异常
+ */
+export function one_last_image_contact_sheet(
+  imageBuffer: Buffer | Uint8Array,
+  config?: Record<string, any>
+): Buffer {
+  const input =
+    imageBuffer instanceof Buffer ? new Uint8Array(imageBuffer) : imageBuffer
+  const configJson =
+    config && Object.keys(config).length > 0
+      ? JSON.stringify(config)
+      : undefined
+  const output = one_last_image_debug_rslib(input, configJson)
+  try {
+    return Buffer.from(output.contact_sheet())
+  } finally {
+    output.free()
+  }
+}
+
+/**
+ * 读取图片元信息，不进行完整解码
+ * @param imageBuffer 图片的 Buffer 或 Uint8Array
+ * @param config 可选配置对象（max_width / max_height / max_alloc 生效）
+ * @returns 图片元信息，无法识别或超出解码限制时抛出异常
+ */
+export function probe(
+  imageBuffer: Buffer | Uint8Array,
+  config?: Record<string, any>
+): ImageInfo {
+  const input =
+    imageBuffer instanceof Buffer ? new Uint8Array(imageBuffer) : imageBuffer
+  const configJson =
+    config && Object.keys(config).length > 0
+      ? JSON.stringify(config)
+      : undefined
+  return JSON.parse(probe_rslib(input, configJson))
+}
+
+/**
+ * 按配置展开处理流水线，用于检查自定义流水线（config.pipeline）
+ * @param config 可选配置对象
+ * @returns 依次执行的阶段名称，配置无效（未知阶段、参数类型错误、TOML 无法解析等）时抛出异常
+ */
+export function pipeline_stages(config?: Record<string, any>): string[] {
+  const configJson =
+    config && Object.keys(config).length > 0
+      ? JSON.stringify(config)
+      : undefined
+  return JSON.parse(pipeline_stages_rslib(configJson))
+}
+
+/**
+ * 读取 mark_id 写入的不可见来源标记
+ * （PNG 保存与轻度 JPEG 重压缩后仍可读取，经过裁剪或缩放后无法读取）
+ * @param imageBuffer 图片的 Buffer 或 Uint8Array
+ * @param config 可选配置对象（max_width / max_height / max_alloc 生效）
+ * @returns 标记的 ID，没有标记时返回 undefined；图片无法解码时抛出异常
+ */
+export function detect_mark(
+  imageBuffer: Buffer | Uint8Array,
+  config?: Record<string, any>
+): string | undefined {
+  const input =
+    imageBuffer instanceof Buffer ? new Uint8Array(imageBuffer) : imageBuffer
+  const configJson =
+    config && Object.keys(config).length > 0
+      ? JSON.stringify(config)
+      : undefined
+  return detect_mark_rslib(input, configJson) ?? undefined
+}
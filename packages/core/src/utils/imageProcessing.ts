@@ -1,5 +1,12 @@
 import { logger } from '../index'
-import { one_last_image } from '../wasm/bindings'
+import {
+  detect_mark,
+  one_last_image,
+  one_last_image_debug,
+  one_last_image_with_info,
+  pipeline_stages,
+  probe
+} from '../wasm/bindings'
 
 export async function processOneLastImage(
   imageBuffer: Buffer,
@@ -24,3 +31,87 @@ export async function processOneLastImage(
     return imageBuffer
   }
 }
+
+export async function processOneLastImageWithInfo(
+  imageBuffer: Buffer,
+  config?: Record<string, any>
+): Promise<{ image: Buffer; info?: Record<string, any> }> {
+  try {
+    const configJson =
+      config && Object.keys(config).length > 0
+        ? JSON.stringify(config)
+        : undefined
+
+    const out = one_last_image_with_info(
+      new Uint8Array(imageBuffer),
+      configJson
+    )
+    try {
+      return { image: Buffer.from(out.image), info: JSON.parse(out.info) }
+    } finally {
+      out.free()
+    }
+  } catch (err) {
+    logger?.warn?.(
+      'processOneLastImageWithInfo: decode failed, return original buffer',
+      {
+        err
+      }
+    )
+    return { image: imageBuffer }
+  }
+}
+
+// 各阶段中间结果的拼图，处理失败时返回 undefined
+export async function processContactSheet(
+  imageBuffer: Buffer,
+  config?: Record<string, any>
+): Promise<Buffer | undefined> {
+  try {
+    const configJson =
+      config && Object.keys(config).length > 0
+        ? JSON.stringify(config)
+        : undefined
+
+    const out = one_last_image_debug(new Uint8Array(imageBuffer), configJson)
+    try {
+      return Buffer.from(out.contact_sheet())
+    } finally {
+      out.free()
+    }
+  } catch (err) {
+    logger?.warn?.('processContactSheet: processing failed', { err })
+    return undefined
+  }
+}
+
+export function probeImage(
+  imageBuffer: Buffer,
+  config?: Record<string, any>
+): Record<string, any> | undefined {
+  try {
+    const configJson =
+      config && Object.keys(config).length > 0
+        ? JSON.stringify(config)
+        : undefined
+
+    return JSON.parse(probe(new Uint8Array(imageBuffer), configJson))
+  } catch (err) {
+    logger?.warn?.('probeImage: unsupported or oversized image', { err })
+    return undefined
+  }
+}
+
+// 配置无效时抛出异常，错误信息可以直接展示给用户
+export function pipelineStages(config: Record<string, any>): string[] {
+  return JSON.parse(pipeline_stages(JSON.stringify(config)))
+}
+
+export function detectMark(imageBuffer: Buffer): string | undefined {
+  try {
+    return detect_mark(new Uint8Array(imageBuffer)) ?? undefined
+  } catch (err) {
+    logger?.warn?.('detectMark: unsupported or oversized image', { err })
+    return undefined
+  }
+}
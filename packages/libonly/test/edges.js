@@ -0,0 +1,295 @@
+/**
+ * One Last Image 边缘对称性测试
+ * 生成合成测试图，检查亮→暗与暗→亮、各个方向的边缘被同等地抗锯齿
+ */
+
+import { one_last_image } from '../lib/index.mjs'
+import { existsSync, mkdirSync, writeFileSync } from 'node:fs'
+import { join, dirname } from 'node:path'
+import { fileURLToPath } from 'node:url'
+import { inflateSync } from 'node:zlib'
+
+const __filename = fileURLToPath(import.meta.url)
+const __dirname = dirname(__filename)
+
+const SIZE = 96
+
+// 只保留线稿与抗锯齿相关的阶段，输出灰度图
+const CONFIG = {
+  quality: 'sketch',
+  kiss: false,
+  watermark: false
+}
+
+// 需要测试的抗锯齿算法
+const ANTIALIAS_MODES = ['blur', 'smaa', 'fxaa', 'ssaa']
+
+// 测试图案：返回 0-255 的灰度值
+const PATTERNS = [
+  {
+    name: 'step-vertical',
+    description: '竖直阶跃边缘',
+    pixel: (x, _y) => (x < SIZE / 2 ? 40 : 220)
+  },
+  {
+    name: 'step-horizontal',
+    description: '水平阶跃边缘',
+    pixel: (_x, y) => (y < SIZE / 2 ? 40 : 220)
+  },
+  {
+    name: 'diagonal',
+    description: '斜线边缘',
+    pixel: (x, y) => (y < x * 0.6 + 10 ? 40 : 220)
+  },
+  {
+    name: 'disc',
+    description: '圆形边缘',
+    pixel: (x, y) =>
+      (x - SIZE / 2) ** 2 + (y - SIZE / 2) ** 2 < (SIZE / 3) ** 2 ? 40 : 220
+  },
+  {
+    name: 'line',
+    description: '细线',
+    pixel: (x, y) => (Math.abs(y - (x * 0.35 + 20)) < 1.5 ? 30 : 230)
+  }
+]
+
+// 变换：对输入做变换后处理，结果应等于对原输出做同样的变换
+const TRANSFORMS = [
+  {
+    name: 'invert',
+    description: '亮暗反转',
+    input: (img) => mapPixels(img, (v) => 255 - v),
+    expected: (img) => mapPixels(img, (v) => 255 - v)
+  },
+  {
+    name: 'flip-x',
+    description: '水平翻转',
+    input: (img) => flipX(img),
+    expected: (img) => flipX(img)
+  },
+  {
+    name: 'flip-y',
+    description: '垂直翻转',
+    input: (img) => flipY(img),
+    expected: (img) => flipY(img)
+  }
+]
+
+// 允许的量化误差
+const TOLERANCE = 1
+
+function log(message, type = 'info') {
+  const colors = {
+    info: '\x1b[36m',
+    success: '\x1b[32m',
+    error: '\x1b[31m',
+    warn: '\x1b[33m'
+  }
+  const reset = '\x1b[0m'
+  console.log(`${colors[type]}${message}${reset}`)
+}
+
+function makeImage(pixel) {
+  const data = new Uint8Array(SIZE * SIZE)
+  for (let y = 0; y < SIZE; y++) {
+    for (let x = 0; x < SIZE; x++) {
+      data[y * SIZE + x] = pixel(x, y)
+    }
+  }
+  return { width: SIZE, height: SIZE, data }
+}
+
+function mapPixels(img, fn) {
+  return { ...img, data: img.data.map(fn) }
+}
+
+function flipX(img) {
+  const data = new Uint8Array(img.data.length)
+  for (let y = 0; y < img.height; y++) {
+    for (let x = 0; x < img.width; x++) {
+      data[y * img.width + x] = img.data[y * img.width + (img.width - 1 - x)]
+    }
+  }
+  return { ...img, data }
+}
+
+function flipY(img) {
+  const data = new Uint8Array(img.data.length)
+  for (let y = 0; y < img.height; y++) {
+    for (let x = 0; x < img.width; x++) {
+      data[y * img.width + x] = img.data[(img.height - 1 - y) * img.width + x]
+    }
+  }
+  return { ...img, data }
+}
+
+// 编码为24位BMP（自底向上，每行4字节对齐）
+function encodeBmp(img) {
+  const rowSize = Math.ceil((img.width * 3) / 4) * 4
+  const pixelBytes = rowSize * img.height
+  const buf = Buffer.alloc(54 + pixelBytes)
+
+  buf.write('BM', 0)
+  buf.writeUInt32LE(54 + pixelBytes, 2)
+  buf.writeUInt32LE(54, 10)
+  buf.writeUInt32LE(40, 14)
+  buf.writeInt32LE(img.width, 18)
+  buf.writeInt32LE(img.height, 22)
+  buf.writeUInt16LE(1, 26)
+  buf.writeUInt16LE(24, 28)
+  buf.writeUInt32LE(pixelBytes, 34)
+
+  for (let y = 0; y < img.height; y++) {
+    const offset = 54 + (img.height - 1 - y) * rowSize
+    for (let x = 0; x < img.width; x++) {
+      const v = img.data[y * img.width + x]
+      buf[offset + x * 3] = v
+      buf[offset + x * 3 + 1] = v
+      buf[offset + x * 3 + 2] = v
+    }
+  }
+  return buf
+}
+
+// 解码库输出的8位RGBA PNG，取R通道作为灰度
+function decodePng(buf) {
+  let offset = 8
+  let width = 0
+  let height = 0
+  const idat = []
+
+  while (offset < buf.length) {
+    const length = buf.readUInt32BE(offset)
+    const type = buf.toString('ascii', offset + 4, offset + 8)
+    const chunk = buf.subarray(offset + 8, offset + 8 + length)
+    if (type === 'IHDR') {
+      width = chunk.readUInt32BE(0)
+      height = chunk.readUInt32BE(4)
+      if (chunk[8] !== 8 || chunk[9] !== 6) {
+        throw new Error('仅支持8位RGBA PNG')
+      }
+    } else if (type === 'IDAT') {
+      idat.push(chunk)
+    }
+    offset += 12 + length
+  }
+
+  const raw = inflateSync(Buffer.concat(idat))
+  const bpp = 4
+  const stride = width * bpp
+  const pixels = new Uint8Array(stride * height)
+
+  for (let y = 0; y < height; y++) {
+    const filter = raw[y * (stride + 1)]
+    const line = raw.subarray(y * (stride + 1) + 1, (y + 1) * (stride + 1))
+    for (let i = 0; i < stride; i++) {
+      const a = i >= bpp ? pixels[y * stride + i - bpp] : 0
+      const b = y > 0 ? pixels[(y - 1) * stride + i] : 0
+      const c = i >= bpp && y > 0 ? pixels[(y - 1) * stride + i - bpp] : 0
+      let predictor = 0
+      if (filter === 1) predictor = a
+      else if (filter === 2) predictor = b
+      else if (filter === 3) predictor = (a + b) >> 1
+      else if (filter === 4) {
+        const p = a + b - c
+        const pa = Math.abs(p - a)
+        const pb = Math.abs(p - b)
+        const pc = Math.abs(p - c)
+        predictor = pa <= pb && pa <= pc ? a : pb <= pc ? b : c
+      }
+      pixels[y * stride + i] = (line[i] + predictor) & 0xff
+    }
+  }
+
+  const data = new Uint8Array(width * height)
+  for (let i = 0; i < data.length; i++) {
+    data[i] = pixels[i * bpp]
+  }
+  return { width, height, data }
+}
+
+function render(img, antialias) {
+  return decodePng(one_last_image(encodeBmp(img), { ...CONFIG, antialias }))
+}
+
+// 统计处于中间灰阶（被抗锯齿混合过）的像素数
+function countBlended(img) {
+  return img.data.filter((v) => v > 40 && v < 220).length
+}
+
+function maxDiff(a, b) {
+  let max = 0
+  for (let i = 0; i < a.data.length; i++) {
+    max = Math.max(max, Math.abs(a.data[i] - b.data[i]))
+  }
+  return max
+}
+
+async function testEdges() {
+  log('='.repeat(60), 'info')
+  log('One Last Image 边缘对称性测试', 'info')
+  log('='.repeat(60), 'info')
+
+  const outputDir = join(__dirname, 'output')
+  if (!existsSync(outputDir)) {
+    mkdirSync(outputDir, { recursive: true })
+  }
+
+  let total = 0
+  let failCount = 0
+
+  for (const antialias of ANTIALIAS_MODES) {
+    for (const pattern of PATTERNS) {
+      log(
+        `\n抗锯齿: ${antialias} 图案: ${pattern.description} (${pattern.name})`,
+        'info'
+      )
+      const input = makeImage(pattern.pixel)
+      const result = one_last_image(encodeBmp(input), { ...CONFIG, antialias })
+      writeFileSync(
+        join(outputDir, `edges-${antialias}-${pattern.name}.png`),
+        result
+      )
+      const output = decodePng(result)
+
+      const blended = countBlended(output)
+      total++
+      if (blended > 0) {
+        log(`✓ 抗锯齿像素: ${blended}`, 'success')
+      } else {
+        log('✗ 没有检测到抗锯齿像素', 'error')
+        failCount++
+      }
+
+      for (const transform of TRANSFORMS) {
+        total++
+        const actual = render(transform.input(input), antialias)
+        const expected = transform.expected(output)
+        const diff = maxDiff(actual, expected)
+        if (diff <= TOLERANCE) {
+          log(`✓ ${transform.description}: 最大差异 ${diff}`, 'success')
+        } else {
+          log(`✗ ${transform.description}: 最大差异 ${diff}`, 'error')
+          failCount++
+        }
+      }
+    }
+  }
+
+  log(`\n${'='.repeat(60)}`, 'info')
+  log('测试总结', 'info')
+  log('='.repeat(60), 'info')
+  log(`总测试数: ${total}`, 'info')
+  log(`成功: ${total - failCount}`, 'success')
+  if (failCount > 0) {
+    log(`失败: ${failCount}`, 'error')
+    process.exit(1)
+  }
+}
+
+testEdges().catch((error) => {
+  log(`\n测试运行失败: ${error.message}`, 'error')
+  console.error(error)
+  process.exit(1)
+})
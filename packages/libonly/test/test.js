@@ -62,6 +62,24 @@ const TEST_CONFIGS = [
       kiss: true,
       light: 10
     }
+  },
+  {
+    name: 'pipeline',
+    description: '自定义流水线（XDoG + 双色调，无水印）',
+    config: {
+      pipeline: [
+        { stage: 'resize' },
+        { stage: 'gray' },
+        { stage: 'denoise' },
+        { stage: 'lines', line_extractor: 'xdog' },
+        { stage: 'antialias' },
+        {
+          stage: 'duotone',
+          duotone_dark: '#1b2a49',
+          duotone_light: '#f4e9d8'
+        }
+      ]
+    }
   }
 ]
 
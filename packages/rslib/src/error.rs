@@ -0,0 +1,40 @@
+use std::fmt;
+
+// 处理过程中的错误，在wasm边界转换为JS异常
+#[derive(Debug)]
+pub enum OLIError {
+    // 无法识别的图片格式
+    UnsupportedFormat,
+    // 图片尺寸或内存占用超过解码限制
+    LimitsExceeded(String),
+    // 解码失败（文件损坏等）
+    Decode(String),
+    // PNG编码失败
+    Encode(String),
+    // 配置项取值无效
+    InvalidConfig(String),
+}
+
+impl fmt::Display for OLIError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OLIError::UnsupportedFormat => write!(f, "unsupported image format"),
+            OLIError::LimitsExceeded(msg) => write!(f, "image exceeds decode limits: {msg}"),
+            OLIError::Decode(msg) => write!(f, "failed to decode image: {msg}"),
+            OLIError::Encode(msg) => write!(f, "failed to encode image: {msg}"),
+            OLIError::InvalidConfig(msg) => write!(f, "invalid config: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for OLIError {}
+
+impl From<image::ImageError> for OLIError {
+    fn from(err: image::ImageError) -> Self {
+        match err {
+            image::ImageError::Limits(e) => OLIError::LimitsExceeded(e.to_string()),
+            image::ImageError::Unsupported(_) => OLIError::UnsupportedFormat,
+            e => OLIError::Decode(e.to_string()),
+        }
+    }
+}
@@ -1,41 +1,338 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
-mod utils;
+pub mod error;
+pub mod utils;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 pub struct OLIConfig {
     pub zoom: Option<f32>,
     pub cover: Option<bool>,
     pub quality: Option<String>,
+    // 高通滤波卷积核：直径（奇数）或半径（可为小数，优先于 kernel_size），设置后覆盖 quality
+    pub kernel_size: Option<u32>,
+    pub kernel_radius: Option<f32>,
+    // 卷积核形状：box（默认）、gaussian、disk
+    pub kernel_shape: Option<String>,
+    // 线稿提取算法：highpass（默认）、dog、xdog、canny
+    pub line_extractor: Option<String>,
+    // dog/xdog/canny 的高斯 sigma
+    pub line_sigma: Option<f32>,
+    // dog/xdog 的两个高斯 sigma 之比
+    pub dog_k: Option<f32>,
+    // xdog 锐化强度、软阈值（0-255）与过渡陡峭程度
+    pub xdog_p: Option<f32>,
+    pub xdog_epsilon: Option<f32>,
+    pub xdog_phi: Option<f32>,
+    // canny 双阈值（Sobel 梯度幅值）
+    pub canny_low: Option<f32>,
+    pub canny_high: Option<f32>,
+    // JPEG去块/去振铃：不设置时对估算质量低于 deblock_quality 的JPEG自动启用
+    pub deblock: Option<bool>,
+    pub deblock_quality: Option<u32>,
     pub denoise: Option<bool>,
+    // 去噪算法：gaussian（默认）、median、bilateral、guided、nlmeans
+    pub denoise_mode: Option<String>,
+    // 去噪强度，1 为默认强度
+    pub denoise_strength: Option<f32>,
+    // 局部对比度增强：none（默认）、clahe、local，在提取线稿前作用于灰度图
+    pub contrast: Option<String>,
+    // 每个方向上的分块数与 CLAHE 对比度限制（平均每桶数量的倍数）
+    pub clahe_tiles: Option<u32>,
+    pub clahe_clip: Option<f32>,
+    // 根据直方图自动选择 light_cut/dark_cut
+    pub auto_levels: Option<bool>,
+    // 按目标墨迹覆盖率（百分比）自动选择 light_cut/dark_cut，优先于 auto_levels
+    pub auto_cut: Option<bool>,
+    pub ink_coverage: Option<f32>,
     pub light_cut: Option<u8>,
     pub dark_cut: Option<u8>,
+    // 线条粗细：结构元素半径（像素），正数加粗、负数变细
+    pub line_width: Option<f32>,
+    // 结构元素形状：disk（默认）、square、diamond
+    pub line_shape: Option<String>,
     pub shade: Option<bool>,
     pub shade_limit: Option<u8>,
     pub shade_light: Option<u8>,
     pub kiss: Option<bool>,
+    // duotone 阶段的暗部与亮部颜色（#rrggbb 或 #rrggbbaa，默认黑与白）
+    pub duotone_dark: Option<String>,
+    pub duotone_light: Option<String>,
     pub watermark: Option<bool>,
     // base64-encoded watermark image data (no data:* prefix), e.g. one-last-image-logo2.png
     pub watermark_image: Option<String>,
+    // 初回样式水印，等同于 watermark_sprite: 1（精灵图只有一格时忽略）
     pub hajimei: Option<bool>,
+    // 文字水印（可用 \n 换行），设置后代替图片水印，颜色跟随 kiss（渐变色或深灰）
+    pub watermark_text: Option<String>,
+    // base64-encoded TTF/OTF font for watermark_text (no data:* prefix), defaults to the bundled font
+    pub watermark_font: Option<String>,
+    // 水印精灵图的行列数与使用第几格（行优先，从0开始）；
+    // 内置水印默认 2 行 1 列，自定义水印默认 1 行 1 列（整张图）
+    pub watermark_rows: Option<u32>,
+    pub watermark_cols: Option<u32>,
+    pub watermark_sprite: Option<u32>,
+    // 水印位置：九宫格锚点（top-left、top、top-right、left、center、right、bottom-left、bottom、bottom-right，默认 bottom-right），
+    // 或用 watermark_x/watermark_y 指定水印中心的相对坐标（0-1）
+    pub watermark_position: Option<String>,
+    pub watermark_x: Option<f32>,
+    pub watermark_y: Option<f32>,
+    // 水印大小倍数、不透明度（0-1）、边距（水印高度的倍数）与顺时针旋转角度
+    pub watermark_scale: Option<f32>,
+    pub watermark_opacity: Option<f32>,
+    pub watermark_margin_x: Option<f32>,
+    pub watermark_margin_y: Option<f32>,
+    pub watermark_rotation: Option<f32>,
+    // 水印绘制方式：single（默认）、tile（平铺并保留单个水印）、tile-only
+    pub watermark_mode: Option<String>,
+    // 平铺水印的倾斜角度（顺时针，默认 -30）、间距（水印高度的倍数，默认 1）与不透明度（默认 0.15）
+    pub watermark_tile_angle: Option<f32>,
+    pub watermark_tile_spacing: Option<f32>,
+    pub watermark_tile_opacity: Option<f32>,
+    // 海报模板：把线稿放进指定大小的画布，并叠加图片、文字与边框
+    pub template: Option<TemplateConfig>,
+    // 不可见的来源标记（UTF-8 最多13字节），可用 detect_mark 读回
+    pub mark_id: Option<String>,
     pub tone_count: Option<u8>,
     pub light: Option<f32>,
+    // 色调曲线：curve 作用于提取线稿前的灰度图，output_curve 作用于着色前的线稿
+    pub curve: Option<CurveConfig>,
+    pub output_curve: Option<CurveConfig>,
     // base64-encoded pencil texture image data (no data:* prefix)
     pub pencil_texture: Option<String>,
+    // 解码限制：最大宽高（像素）与最大内存占用（字节）
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    pub max_alloc: Option<u64>,
+    // 抗锯齿算法：blur（默认）、smaa、fxaa、ssaa、none
+    pub antialias: Option<String>,
+    // 抗锯齿边缘阈值（0-255亮度单位），不设置时使用各算法的默认值
+    pub antialias_threshold: Option<f32>,
+    // 锐化：开关、强度、半径（高斯sigma）、阈值（低于该对比度的像素不锐化）
+    pub sharpen: Option<bool>,
+    pub sharpen_amount: Option<f32>,
+    pub sharpen_radius: Option<f32>,
+    pub sharpen_threshold: Option<f32>,
+    // 自定义流水线：按顺序执行的阶段列表，设置后代替默认流程
+    pub pipeline: Option<PipelineDefinition>,
 }
 
-// One Last Image 主函数 - 将图片转换为线稿效果
-// config_json 为可选参数，传入空字符串或无效JSON时使用默认配置
-#[wasm_bindgen]
-pub fn one_last_image(input: &[u8], config_json: Option<String>) -> Vec<u8> {
-    let config = config_json.and_then(|s| {
-        if s.is_empty() {
-            None
-        } else {
-            serde_json::from_str::<OLIConfig>(&s).ok()
+// 色调曲线：黑白场与gamma，或控制点（0-255 的 [输入, 输出] 对，按单调三次样条插值）
+// 两者同时设置时先做色阶映射再套用控制点
+#[derive(Deserialize, Serialize)]
+pub struct CurveConfig {
+    pub gamma: Option<f32>,
+    pub black_point: Option<f32>,
+    pub white_point: Option<f32>,
+    pub points: Option<Vec<[f32; 2]>>,
+}
+
+// 海报模板：画布大小与背景色（#rrggbb 或 #rrggbbaa，默认白色），线稿放置的区域（默认整个画布）
+// 与适配方式（contain（默认）、cover、stretch），layers 按顺序绘制在线稿之上
+#[derive(Deserialize, Serialize, Clone)]
+pub struct TemplateConfig {
+    pub width: u32,
+    pub height: u32,
+    pub background: Option<String>,
+    pub drawing: Option<TemplateRect>,
+    pub fit: Option<String>,
+    pub layers: Option<Vec<TemplateLayer>>,
+}
+
+// 画布上的矩形区域（像素），可以部分超出画布
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub struct TemplateRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TemplateLayer {
+    // base64-encoded image (no data:* prefix), fitted into rect like the drawing
+    Image {
+        image: String,
+        rect: TemplateRect,
+        fit: Option<String>,
+        opacity: Option<f32>,
+    },
+    // 文字：字号为像素高度，color 为颜色或 kiss（渐变色，默认跟随 kiss 设置），
+    // align 为 left（默认）、center、right，对齐到 x 起宽 width 的范围（不设置 width 时对齐到 x）
+    // font 为 base64 编码的 TTF/OTF 字体，默认使用内置字体
+    Text {
+        text: String,
+        x: i32,
+        y: i32,
+        width: Option<u32>,
+        size: f32,
+        color: Option<String>,
+        align: Option<String>,
+        font: Option<String>,
+    },
+    // 矩形边框（向内绘制 thickness 像素，默认 1）与可选的填充色
+    Border {
+        rect: TemplateRect,
+        thickness: Option<u32>,
+        color: Option<String>,
+        fill: Option<String>,
+    },
+}
+
+// 自定义流水线：阶段对象的数组，或包含 [[pipeline]] 表的 TOML 字符串
+// 每个阶段对象的 stage 为阶段名称，其余字段与配置项同名，只对该阶段生效，没有设置的使用顶层配置
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum PipelineDefinition {
+    Stages(Vec<serde_json::Map<String, serde_json::Value>>),
+    Toml(String),
+}
+
+// 空字符串或无效JSON时使用默认配置；设置了 pipeline 但无法解析时报错，不能静默退回默认流程
+fn parse_config(config_json: Option<String>) -> Result<Option<OLIConfig>, JsError> {
+    let Some(s) = config_json.filter(|s| !s.is_empty()) else {
+        return Ok(None);
+    };
+    match serde_json::from_str::<OLIConfig>(&s) {
+        Ok(config) => Ok(Some(config)),
+        Err(e) => {
+            let has_pipeline = serde_json::from_str::<serde_json::Value>(&s)
+                .is_ok_and(|value| value.get("pipeline").is_some());
+            if has_pipeline {
+                let err = error::OLIError::InvalidConfig(format!("pipeline config: {e}"));
+                return Err(JsError::new(&err.to_string()));
+            }
+            Ok(None)
         }
-    });
+    }
+}
+
+// One Last Image 主函数 - 将图片转换为线稿效果
+// config_json 为可选参数，传入空字符串或无效JSON时使用默认配置，设置了 pipeline 但无法解析时抛出异常
+// 图片无法解码或超出解码限制时抛出异常
+#[wasm_bindgen]
+pub fn one_last_image(input: &[u8], config_json: Option<String>) -> Result<Vec<u8>, JsError> {
+    let config = parse_config(config_json)?;
 
     utils::image_processing::one_last_image_with_config(input, config)
+        .map_err(|e| JsError::new(&e.to_string()))
+}
+
+// 处理结果：PNG图片与实际使用的参数（JSON字符串）
+#[wasm_bindgen]
+pub struct OLIOutput {
+    image: Vec<u8>,
+    info: String,
+}
+
+#[wasm_bindgen]
+impl OLIOutput {
+    #[wasm_bindgen(getter)]
+    pub fn image(&self) -> Vec<u8> {
+        self.image.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn info(&self) -> String {
+        self.info.clone()
+    }
+}
+
+// 与 one_last_image 相同，同时返回实际使用的 light_cut/dark_cut 与墨迹覆盖率，
+// 便于展示 auto_cut / auto_levels 选出的值或在之后的请求中复用
+#[wasm_bindgen]
+pub fn one_last_image_with_info(
+    input: &[u8],
+    config_json: Option<String>,
+) -> Result<OLIOutput, JsError> {
+    let config = parse_config(config_json)?;
+
+    let (image, info) = utils::image_processing::one_last_image_with_info(input, config)
+        .map_err(|e| JsError::new(&e.to_string()))?;
+    let info = serde_json::to_string(&info).map_err(|e| JsError::new(&e.to_string()))?;
+    Ok(OLIOutput { image, info })
+}
+
+// 调试输出：按顺序保存每个阶段执行后的中间结果（灰度、降噪、线稿、截断、抗锯齿的边缘图与混合权重、锐化、着色等）
+#[wasm_bindgen]
+pub struct OLIDebug {
+    stages: Vec<(String, image::RgbaImage)>,
+}
+
+#[wasm_bindgen]
+impl OLIDebug {
+    // 各中间结果的名称，与阶段名称相同；抗锯齿额外有 antialias_edges 与 antialias_weights
+    #[wasm_bindgen(getter)]
+    pub fn names(&self) -> Vec<String> {
+        self.stages.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    // 第 index 个中间结果的PNG图片，超出范围时返回 undefined
+    pub fn image(&self, index: usize) -> Result<Option<Vec<u8>>, JsError> {
+        self.stages
+            .get(index)
+            .map(|(_, image)| utils::image_processing::encode_png(image))
+            .transpose()
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    // 把所有中间结果拼成一张带名称标注的PNG图片
+    pub fn contact_sheet(&self) -> Result<Vec<u8>, JsError> {
+        let sheet = utils::debug::contact_sheet(&self.stages);
+        utils::image_processing::encode_png(&sheet).map_err(|e| JsError::new(&e.to_string()))
+    }
+}
+
+// 以调试模式处理图片，参数与 one_last_image 相同，返回每个阶段的中间结果
+// 中间结果按比例缩小到边长 320 以内，只用于查看各阶段的效果
+#[wasm_bindgen]
+pub fn one_last_image_debug(
+    input: &[u8],
+    config_json: Option<String>,
+) -> Result<OLIDebug, JsError> {
+    let config = parse_config(config_json)?;
+
+    let stages = utils::pipeline::Pipeline::from_config(config.as_ref())
+        .and_then(|pipeline| pipeline.run_debug(input))
+        .map_err(|e| JsError::new(&e.to_string()))?;
+    Ok(OLIDebug { stages })
+}
+
+// 读取图片元信息（格式、尺寸、帧数、颜色类型、位深、EXIF方向、透明通道），返回JSON字符串
+// config_json 中的 max_width / max_height / max_alloc 同样生效
+#[wasm_bindgen]
+pub fn probe(input: &[u8], config_json: Option<String>) -> Result<String, JsError> {
+    let config = parse_config(config_json)?;
+    let limits = utils::decode::DecodeLimits::from_config(config.as_ref());
+
+    let info = utils::decode::probe(input, &limits).map_err(|e| JsError::new(&e.to_string()))?;
+    serde_json::to_string(&info).map_err(|e| JsError::new(&e.to_string()))
+}
+
+// 按配置展开处理流水线，返回各阶段名称的JSON数组，可用于检查自定义流水线
+// 与其他函数不同，config_json 不是有效的配置时抛出异常
+#[wasm_bindgen]
+pub fn pipeline_stages(config_json: Option<String>) -> Result<String, JsError> {
+    let config = config_json
+        .filter(|s| !s.is_empty())
+        .map(|s| serde_json::from_str::<OLIConfig>(&s))
+        .transpose()
+        .map_err(|e| JsError::new(&e.to_string()))?;
+
+    let pipeline = utils::pipeline::Pipeline::from_config(config.as_ref())
+        .map_err(|e| JsError::new(&e.to_string()))?;
+    serde_json::to_string(&pipeline.names()).map_err(|e| JsError::new(&e.to_string()))
+}
+
+// 读取 mark_id 写入的不可见来源标记，没有标记时返回 undefined
+// 经过裁剪或缩放的图片无法读取；config_json 中的 max_width / max_height / max_alloc 同样生效
+#[wasm_bindgen]
+pub fn detect_mark(input: &[u8], config_json: Option<String>) -> Result<Option<String>, JsError> {
+    let config = parse_config(config_json)?;
+    let limits = utils::decode::DecodeLimits::from_config(config.as_ref());
+
+    let img =
+        utils::decode::load_image(input, &limits).map_err(|e| JsError::new(&e.to_string()))?;
+    Ok(utils::provenance::detect_mark(&img.to_rgba8()))
 }
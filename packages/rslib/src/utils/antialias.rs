@@ -0,0 +1,661 @@
+use rayon::prelude::*;
+
+use super::convolution::sobel_gradients;
+use crate::error::OLIError;
+
+// 超采样抗锯齿的倍率
+pub const SSAA_FACTOR: u32 = 2;
+
+// 抗锯齿算法
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AntialiasMode {
+    // 梯度门限的3x3加权模糊（默认，与早期版本输出一致）
+    EdgeBlur,
+    // 形态学抗锯齿：沿边缘搜索线段长度，按覆盖面积混合
+    Smaa,
+    // 快速近似抗锯齿
+    Fxaa,
+    // 以 SSAA_FACTOR 倍分辨率提取线稿后缩小
+    Ssaa,
+    None,
+}
+
+impl AntialiasMode {
+    pub fn from_name(name: &str) -> Result<Self, OLIError> {
+        match name {
+            "blur" => Ok(AntialiasMode::EdgeBlur),
+            "smaa" => Ok(AntialiasMode::Smaa),
+            "fxaa" => Ok(AntialiasMode::Fxaa),
+            "ssaa" => Ok(AntialiasMode::Ssaa),
+            "none" => Ok(AntialiasMode::None),
+            _ => Err(OLIError::InvalidConfig(format!(
+                "unknown antialias mode \"{name}\""
+            ))),
+        }
+    }
+
+    // 各算法的默认边缘阈值（0-255亮度单位）
+    pub fn default_threshold(self) -> f32 {
+        match self {
+            AntialiasMode::EdgeBlur => 20.0,
+            AntialiasMode::Smaa => 0.1 * 255.0,
+            AntialiasMode::Fxaa => 0.0625 * 255.0,
+            AntialiasMode::Ssaa | AntialiasMode::None => 0.0,
+        }
+    }
+
+    // 处理时的分辨率倍率
+    pub fn scale(self) -> u32 {
+        if self == AntialiasMode::Ssaa {
+            SSAA_FACTOR
+        } else {
+            1
+        }
+    }
+}
+
+// 按所选算法做抗锯齿，SSAA 时输入为放大后的缓冲区，输出缩小回原尺寸
+pub fn antialias(
+    pixels: &[f32],
+    width: u32,
+    height: u32,
+    mode: AntialiasMode,
+    threshold: f32,
+) -> Vec<f32> {
+    match mode {
+        AntialiasMode::EdgeBlur => edge_blur_antialiasing(pixels, width, height, threshold),
+        AntialiasMode::Smaa => smaa_antialiasing(pixels, width, height, threshold),
+        AntialiasMode::Fxaa => fxaa_antialiasing(pixels, width, height, threshold),
+        AntialiasMode::Ssaa => downsample(pixels, width, height, SSAA_FACTOR),
+        AntialiasMode::None => pixels.to_vec(),
+    }
+}
+
+// 抗锯齿的中间结果：边缘图与混合权重（0-255，越亮越强），用于调试输出
+// 只有分步计算的 blur 与 smaa 有中间结果
+pub fn antialias_maps(
+    pixels: &[f32],
+    width: u32,
+    height: u32,
+    mode: AntialiasMode,
+    threshold: f32,
+) -> Option<(Vec<f32>, Vec<f32>)> {
+    match mode {
+        AntialiasMode::EdgeBlur => {
+            let edges = edge_blur_detection(pixels, width, height);
+            let weights = edge_blur_weights(&edges, width, height, threshold);
+            Some((
+                edges.iter().map(|e| e.min(255.0)).collect(),
+                weights.iter().map(|w| w * 255.0).collect(),
+            ))
+        }
+        AntialiasMode::Smaa => {
+            let edges = smaa_edge_detection(pixels, width, height, threshold);
+            let weights = smaa_blend_weights(&edges, width, height);
+            Some((
+                edges
+                    .iter()
+                    .map(|&e| if e != 0 { 255.0 } else { 0.0 })
+                    .collect(),
+                weights
+                    .iter()
+                    .map(|[top, bottom, left, right]| {
+                        ((top + bottom).max(left + right) * 255.0).min(255.0)
+                    })
+                    .collect(),
+            ))
+        }
+        AntialiasMode::Fxaa | AntialiasMode::Ssaa | AntialiasMode::None => None,
+    }
+}
+
+// 边缘模糊抗锯齿（默认）- 在梯度较强的位置做加权的3x3模糊
+pub fn edge_blur_antialiasing(pixels: &[f32], width: u32, height: u32, threshold: f32) -> Vec<f32> {
+    // 第一步：边缘检测
+    let edges = edge_blur_detection(pixels, width, height);
+
+    // 第二步：混合权重计算
+    let blend_weights = edge_blur_weights(&edges, width, height, threshold);
+
+    // 第三步：邻域混合
+    edge_blur_blending(pixels, &blend_weights, width, height)
+}
+
+// 边缘模糊 第一步：使用Sobel算子进行边缘检测
+// 梯度带符号，幅值与边缘方向无关，从左到右、从右到左的边缘被同等对待
+fn edge_blur_detection(pixels: &[f32], width: u32, height: u32) -> Vec<f32> {
+    let (gx, gy) = sobel_gradients(pixels, width, height);
+
+    // 并行计算梯度幅值
+    let edges: Vec<f32> = gx
+        .par_iter()
+        .zip(gy.par_iter())
+        .map(|(&gx_val, &gy_val)| (gx_val * gx_val + gy_val * gy_val).sqrt())
+        .collect();
+
+    edges
+}
+
+// 边缘模糊 第二步：计算混合权重
+fn edge_blur_weights(edges: &[f32], width: u32, height: u32, threshold: f32) -> Vec<f32> {
+    // 并行处理每一行
+    let weights: Vec<f32> = (1..(height - 1))
+        .into_par_iter()
+        .flat_map(|y| {
+            let mut row = vec![0.0f32; width as usize];
+
+            for x in 1..(width - 1) {
+                let idx = (y * width + x) as usize;
+                let edge_strength = edges[idx];
+
+                if edge_strength > threshold {
+                    // 计算局部边缘模式
+                    let mut pattern_weight = 0.0;
+                    let mut count = 0.0;
+
+                    for dy in -1..=1 {
+                        for dx in -1..=1 {
+                            let ny = (y as i32 + dy) as u32;
+                            let nx = (x as i32 + dx) as u32;
+                            let nidx = (ny * width + nx) as usize;
+
+                            if edges[nidx] > threshold {
+                                let dist = ((dx * dx + dy * dy) as f32).sqrt();
+                                let weight = 1.0 / (1.0 + dist);
+                                pattern_weight += weight;
+                                count += 1.0;
+                            }
+                        }
+                    }
+
+                    // 归一化权重
+                    row[x as usize] = if count > 0.0 {
+                        (pattern_weight / count).min(1.0)
+                    } else {
+                        0.0
+                    };
+                }
+            }
+            row
+        })
+        .collect();
+
+    // 添加首尾行（全零）
+    let mut result = vec![0.0f32; width as usize];
+    result.extend(weights);
+    result.extend(vec![0.0f32; width as usize]);
+    result
+}
+
+// 边缘模糊 第三步：邻域混合
+fn edge_blur_blending(pixels: &[f32], weights: &[f32], width: u32, height: u32) -> Vec<f32> {
+    // 并行处理每一行
+    let output: Vec<f32> = (0..height)
+        .into_par_iter()
+        .flat_map(|y| {
+            let mut row = vec![0.0f32; width as usize];
+
+            for x in 0..width {
+                let idx = (y * width + x) as usize;
+                let weight = weights[idx];
+
+                if weight < 0.01 {
+                    row[x as usize] = pixels[idx];
+                } else {
+                    // 使用双线性插值进行亚像素混合
+                    let mut sum = pixels[idx] * (1.0 - weight);
+                    let mut total_weight = 1.0 - weight;
+
+                    // 采样周围像素
+                    for dy in -1..=1 {
+                        for dx in -1..=1 {
+                            if dx == 0 && dy == 0 {
+                                continue;
+                            }
+
+                            let ny = (y as i32 + dy).clamp(0, height as i32 - 1) as u32;
+                            let nx = (x as i32 + dx).clamp(0, width as i32 - 1) as u32;
+                            let nidx = (ny * width + nx) as usize;
+
+                            let dist = ((dx * dx + dy * dy) as f32).sqrt();
+                            let sample_weight = weight / (1.0 + dist * 2.0);
+
+                            sum += pixels[nidx] * sample_weight;
+                            total_weight += sample_weight;
+                        }
+                    }
+
+                    row[x as usize] = sum / total_weight;
+                }
+            }
+            row
+        })
+        .collect();
+
+    output
+}
+
+// SMAA 边缘标记位
+const EDGE_TOP: u8 = 1;
+const EDGE_LEFT: u8 = 2;
+
+// 局部对比度自适应系数：比相邻平行边缘弱一半以上的边缘视为噪声
+const SMAA_LOCAL_CONTRAST_FACTOR: f32 = 2.0;
+
+// 计算覆盖面积时每个像素的采样数
+const SMAA_AREA_SAMPLES: usize = 8;
+
+// SMAA (Subpixel Morphological Anti-Aliasing) 实现
+// 与原版相同分为三步，面积表改为按重建的边缘轮廓解析计算
+fn smaa_antialiasing(pixels: &[f32], width: u32, height: u32, threshold: f32) -> Vec<f32> {
+    // 第一步：亮度边缘检测
+    let edges = smaa_edge_detection(pixels, width, height, threshold);
+
+    // 第二步：沿边缘搜索线段并计算混合权重
+    let blend_weights = smaa_blend_weights(&edges, width, height);
+
+    // 第三步：邻域混合
+    smaa_neighborhood_blending(pixels, &blend_weights, width, height)
+}
+
+// SMAA 第一步：比较相邻像素的亮度差，标记像素上方与左侧的边缘
+fn smaa_edge_detection(pixels: &[f32], width: u32, height: u32, threshold: f32) -> Vec<u8> {
+    let w = width as usize;
+    let h = height as usize;
+    let at = |x: usize, y: usize| pixels[y * w + x];
+
+    // 像素(x, y)与上方、左侧像素的亮度差
+    let delta_top = |x: usize, y: usize| {
+        if y == 0 || y >= h {
+            0.0
+        } else {
+            (at(x, y) - at(x, y - 1)).abs()
+        }
+    };
+    let delta_left = |x: usize, y: usize| {
+        if x == 0 || x >= w {
+            0.0
+        } else {
+            (at(x, y) - at(x - 1, y)).abs()
+        }
+    };
+
+    let mut edges = vec![0u8; w * h];
+    edges.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        for (x, edge) in row.iter_mut().enumerate() {
+            let top = delta_top(x, y);
+            if top > threshold {
+                let neighbor = delta_top(x, y.saturating_sub(1)).max(delta_top(x, y + 1));
+                if top * SMAA_LOCAL_CONTRAST_FACTOR >= neighbor {
+                    *edge |= EDGE_TOP;
+                }
+            }
+
+            let left = delta_left(x, y);
+            if left > threshold {
+                let neighbor = delta_left(x.saturating_sub(1), y).max(delta_left(x + 1, y));
+                if left * SMAA_LOCAL_CONTRAST_FACTOR >= neighbor {
+                    *edge |= EDGE_LEFT;
+                }
+            }
+        }
+    });
+
+    edges
+}
+
+// 线段端点处的拐向：+0.5 表示轮廓折向边缘前一侧（上/左），-0.5 表示折向后一侧（下/右）
+fn smaa_end_height(toward_before: bool, toward_after: bool) -> f32 {
+    match (toward_before, toward_after) {
+        (true, false) => 0.5,
+        (false, true) => -0.5,
+        _ => 0.0,
+    }
+}
+
+// 计算一条长度为 length 的边缘线段上每个像素的覆盖面积
+// 按两端拐向重建轮廓（L形连到另一端，Z形/U形连到中点），返回 (前侧面积, 后侧面积)
+fn smaa_area(length: usize, start: f32, end: f32) -> Vec<(f32, f32)> {
+    let len = length as f32;
+    let contour = |t: f32| -> f32 {
+        match (start != 0.0, end != 0.0) {
+            (true, false) => start * (1.0 - t / len),
+            (false, true) => end * (t / len),
+            (true, true) => {
+                let half = len / 2.0;
+                if t < half {
+                    start * (1.0 - t / half)
+                } else {
+                    end * ((t - half) / half)
+                }
+            }
+            (false, false) => 0.0,
+        }
+    };
+
+    (0..length)
+        .map(|i| {
+            let mut before = 0.0;
+            let mut after = 0.0;
+            for k in 0..SMAA_AREA_SAMPLES {
+                let t = i as f32 + (k as f32 + 0.5) / SMAA_AREA_SAMPLES as f32;
+                let h = contour(t);
+                if h > 0.0 {
+                    before += h;
+                } else {
+                    after -= h;
+                }
+            }
+            (
+                before / SMAA_AREA_SAMPLES as f32,
+                after / SMAA_AREA_SAMPLES as f32,
+            )
+        })
+        .collect()
+}
+
+// 在一行（或一列）边缘标记中搜索连续线段，返回每个位置的覆盖面积
+// has_edge(i) 表示第 i 个位置有边缘，crossing(i) 返回该位置两侧是否有垂直方向的边缘（前侧, 后侧）
+fn smaa_search_line(
+    len: usize,
+    has_edge: impl Fn(usize) -> bool,
+    crossing: impl Fn(usize) -> (bool, bool),
+) -> Vec<(f32, f32)> {
+    let mut areas = vec![(0.0, 0.0); len];
+    let mut i = 0;
+    while i < len {
+        if !has_edge(i) {
+            i += 1;
+            continue;
+        }
+
+        // 线段 [start, end)
+        let start = i;
+        while i < len && has_edge(i) {
+            i += 1;
+        }
+        let end = i;
+
+        let (start_before, start_after) = crossing(start);
+        let (end_before, end_after) = if end < len {
+            crossing(end)
+        } else {
+            (false, false)
+        };
+        let start_h = smaa_end_height(start_before, start_after);
+        let end_h = smaa_end_height(end_before, end_after);
+
+        if start_h != 0.0 || end_h != 0.0 {
+            for (area, value) in
+                areas[start..end]
+                    .iter_mut()
+                    .zip(smaa_area(end - start, start_h, end_h))
+            {
+                *area = value;
+            }
+        }
+    }
+    areas
+}
+
+// SMAA 第二步：计算混合权重，每个像素分别记录向上、下、左、右邻居混合的比例
+fn smaa_blend_weights(edges: &[u8], width: u32, height: u32) -> Vec<[f32; 4]> {
+    let w = width as usize;
+    let h = height as usize;
+    let edge = |x: usize, y: usize, flag: u8| edges[y * w + x] & flag != 0;
+
+    // 水平边缘（像素上方）：按行搜索，端点处看上下两行是否有竖直边缘
+    let horizontal: Vec<Vec<(f32, f32)>> = (0..h)
+        .into_par_iter()
+        .map(|y| {
+            if y == 0 {
+                return vec![(0.0, 0.0); w];
+            }
+            smaa_search_line(
+                w,
+                |x| edge(x, y, EDGE_TOP),
+                |x| (edge(x, y - 1, EDGE_LEFT), edge(x, y, EDGE_LEFT)),
+            )
+        })
+        .collect();
+
+    // 竖直边缘（像素左侧）：按列搜索，端点处看左右两列是否有水平边缘
+    let vertical: Vec<Vec<(f32, f32)>> = (0..w)
+        .into_par_iter()
+        .map(|x| {
+            if x == 0 {
+                return vec![(0.0, 0.0); h];
+            }
+            smaa_search_line(
+                h,
+                |y| edge(x, y, EDGE_LEFT),
+                |y| (edge(x - 1, y, EDGE_TOP), edge(x, y, EDGE_TOP)),
+            )
+        })
+        .collect();
+
+    // 前侧面积混合到边缘前面的像素（上/左），后侧面积混合到边缘后面的像素（下/右）
+    let mut weights = vec![[0.0f32; 4]; w * h];
+    weights.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        for (x, weight) in row.iter_mut().enumerate() {
+            let top = horizontal[y][x].1;
+            let bottom = if y + 1 < h {
+                horizontal[y + 1][x].0
+            } else {
+                0.0
+            };
+            let left = vertical[x][y].1;
+            let right = if x + 1 < w { vertical[x + 1][y].0 } else { 0.0 };
+            *weight = [top, bottom, left, right];
+        }
+    });
+
+    weights
+}
+
+// SMAA 第三步：邻域混合，按权重较大的方向（水平或竖直）与相邻像素混合
+fn smaa_neighborhood_blending(
+    pixels: &[f32],
+    weights: &[[f32; 4]],
+    width: u32,
+    height: u32,
+) -> Vec<f32> {
+    let w = width as usize;
+    let h = height as usize;
+
+    let mut output = vec![0.0f32; w * h];
+    output.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        for (x, out) in row.iter_mut().enumerate() {
+            let idx = y * w + x;
+            let [top, bottom, left, right] = weights[idx];
+            let center = pixels[idx];
+
+            *out = if top + bottom < 1e-3 && left + right < 1e-3 {
+                center
+            } else if top + bottom >= left + right {
+                let up = pixels[y.saturating_sub(1) * w + x];
+                let down = pixels[(y + 1).min(h - 1) * w + x];
+                center * (1.0 - top - bottom) + up * top + down * bottom
+            } else {
+                let l = pixels[y * w + x.saturating_sub(1)];
+                let r = pixels[y * w + (x + 1).min(w - 1)];
+                center * (1.0 - left - right) + l * left + r * right
+            };
+        }
+    });
+
+    output
+}
+
+// FXAA 沿边缘搜索的步长
+const FXAA_SEARCH_STEPS: [f32; 12] = [1.0, 1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 4.0, 8.0];
+
+// 双线性采样，像素中心位于整数坐标
+fn sample_bilinear(pixels: &[f32], width: usize, height: usize, x: f32, y: f32) -> f32 {
+    let x = x.clamp(0.0, (width - 1) as f32);
+    let y = y.clamp(0.0, (height - 1) as f32);
+    let x0 = x.floor() as usize;
+    let y0 = y.floor() as usize;
+    let x1 = (x0 + 1).min(width - 1);
+    let y1 = (y0 + 1).min(height - 1);
+    let fx = x - x0 as f32;
+    let fy = y - y0 as f32;
+
+    let top = pixels[y0 * width + x0] * (1.0 - fx) + pixels[y0 * width + x1] * fx;
+    let bottom = pixels[y1 * width + x0] * (1.0 - fx) + pixels[y1 * width + x1] * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+// FXAA (Fast Approximate Anti-Aliasing) 实现
+fn fxaa_antialiasing(pixels: &[f32], width: u32, height: u32, threshold: f32) -> Vec<f32> {
+    let w = width as usize;
+    let h = height as usize;
+    let at = |x: i32, y: i32| {
+        let x = x.clamp(0, w as i32 - 1) as usize;
+        let y = y.clamp(0, h as i32 - 1) as usize;
+        pixels[y * w + x]
+    };
+
+    let mut output = vec![0.0f32; w * h];
+    output.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        let y = y as i32;
+        for (x, out) in row.iter_mut().enumerate() {
+            let x = x as i32;
+            let m = at(x, y);
+            let n = at(x, y - 1);
+            let s = at(x, y + 1);
+            let wl = at(x - 1, y);
+            let e = at(x + 1, y);
+
+            // 局部对比度不足时跳过
+            // 原版FXAA的阈值随亮度变化，这里只用绝对阈值，使亮线与暗线被同等处理
+            let max = m.max(n).max(s).max(wl).max(e);
+            let min = m.min(n).min(s).min(wl).min(e);
+            let range = max - min;
+            if range < threshold {
+                *out = m;
+                continue;
+            }
+
+            let nw = at(x - 1, y - 1);
+            let ne = at(x + 1, y - 1);
+            let sw = at(x - 1, y + 1);
+            let se = at(x + 1, y + 1);
+
+            // 判断边缘走向
+            let edge_h = (nw + sw - 2.0 * wl).abs()
+                + (n + s - 2.0 * m).abs() * 2.0
+                + (ne + se - 2.0 * e).abs();
+            let edge_v = (nw + ne - 2.0 * n).abs()
+                + (wl + e - 2.0 * m).abs() * 2.0
+                + (sw + se - 2.0 * s).abs();
+            let horizontal = edge_h >= edge_v;
+
+            // 选择梯度较大的一侧
+            let (luma1, luma2) = if horizontal { (n, s) } else { (wl, e) };
+            let grad1 = luma1 - m;
+            let grad2 = luma2 - m;
+            let steep1 = grad1.abs() >= grad2.abs();
+            let grad_scaled = 0.25 * grad1.abs().max(grad2.abs());
+            let (step, local_avg) = if steep1 {
+                (-1.0, 0.5 * (luma1 + m))
+            } else {
+                (1.0, 0.5 * (luma2 + m))
+            };
+
+            // 从两像素之间的边缘位置出发，沿边缘两个方向搜索端点
+            let (base_x, base_y, dir_x, dir_y) = if horizontal {
+                (x as f32, y as f32 + step * 0.5, 1.0, 0.0)
+            } else {
+                (x as f32 + step * 0.5, y as f32, 0.0, 1.0)
+            };
+
+            let mut dist1 = 0.0;
+            let mut dist2 = 0.0;
+            let mut end1 = 0.0;
+            let mut end2 = 0.0;
+            let mut reached1 = false;
+            let mut reached2 = false;
+            for &step_len in FXAA_SEARCH_STEPS.iter() {
+                if !reached1 {
+                    dist1 += step_len;
+                    end1 = sample_bilinear(
+                        pixels,
+                        w,
+                        h,
+                        base_x - dir_x * dist1,
+                        base_y - dir_y * dist1,
+                    ) - local_avg;
+                    reached1 = end1.abs() >= grad_scaled;
+                }
+                if !reached2 {
+                    dist2 += step_len;
+                    end2 = sample_bilinear(
+                        pixels,
+                        w,
+                        h,
+                        base_x + dir_x * dist2,
+                        base_y + dir_y * dist2,
+                    ) - local_avg;
+                    reached2 = end2.abs() >= grad_scaled;
+                }
+                if reached1 && reached2 {
+                    break;
+                }
+            }
+
+            // 根据离较近端点的距离估计像素偏移
+            let direction1 = dist1 < dist2;
+            let dist_final = dist1.min(dist2);
+            let pixel_offset = 0.5 - dist_final / (dist1 + dist2);
+            let end_luma = if direction1 { end1 } else { end2 };
+            let center_smaller = m < local_avg;
+            let edge_offset = if (end_luma < 0.0) != center_smaller {
+                pixel_offset
+            } else {
+                0.0
+            };
+
+            // 亚像素锯齿
+            let average = (2.0 * (n + s + wl + e) + nw + ne + sw + se) / 12.0;
+            let sub1 = ((average - m).abs() / range).clamp(0.0, 1.0);
+            let sub2 = (-2.0 * sub1 + 3.0) * sub1 * sub1;
+            let sub_offset = sub2 * sub2 * 0.75;
+
+            let offset = edge_offset.max(sub_offset) * step;
+            *out = if horizontal {
+                sample_bilinear(pixels, w, h, x as f32, y as f32 + offset)
+            } else {
+                sample_bilinear(pixels, w, h, x as f32 + offset, y as f32)
+            };
+        }
+    });
+
+    output
+}
+
+// 按 factor×factor 的块求平均缩小，用于超采样抗锯齿
+pub fn downsample(pixels: &[f32], width: u32, height: u32, factor: u32) -> Vec<f32> {
+    let w = width as usize;
+    let f = factor as usize;
+    let out_w = (width / factor) as usize;
+    let out_h = (height / factor) as usize;
+    let area = (f * f) as f32;
+
+    let mut output = vec![0.0f32; out_w * out_h];
+    output
+        .par_chunks_mut(out_w.max(1))
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, out) in row.iter_mut().enumerate() {
+                let mut sum = 0.0;
+                for dy in 0..f {
+                    let src = &pixels[(y * f + dy) * w + x * f..][..f];
+                    sum += src.iter().sum::<f32>();
+                }
+                *out = sum / area;
+            }
+        });
+
+    output
+}
@@ -0,0 +1,190 @@
+use rayon::prelude::*;
+
+use crate::error::OLIError;
+
+// 直方图分桶数
+const BINS: usize = 256;
+
+// 局部对比度增强方式
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ContrastMode {
+    None,
+    // 限制对比度的自适应直方图均衡
+    Clahe,
+    // 不限制对比度的局部直方图均衡
+    Local,
+}
+
+impl ContrastMode {
+    pub fn from_name(name: &str) -> Result<Self, OLIError> {
+        match name {
+            "none" => Ok(ContrastMode::None),
+            "clahe" => Ok(ContrastMode::Clahe),
+            "local" => Ok(ContrastMode::Local),
+            _ => Err(OLIError::InvalidConfig(format!(
+                "unknown contrast mode \"{name}\""
+            ))),
+        }
+    }
+}
+
+fn bin(v: f32) -> usize {
+    v.round().clamp(0.0, (BINS - 1) as f32) as usize
+}
+
+// 单个分块的映射表：直方图按 clip_limit（平均每桶数量的倍数）截断，多出的部分平均分给所有桶，再取累积分布
+fn tile_mapping(
+    pixels: &[f32],
+    width: usize,
+    (x0, x1): (usize, usize),
+    (y0, y1): (usize, usize),
+    clip_limit: Option<f32>,
+) -> [f32; BINS] {
+    let mut hist = [0.0f32; BINS];
+    for y in y0..y1 {
+        for x in x0..x1 {
+            hist[bin(pixels[y * width + x])] += 1.0;
+        }
+    }
+    let total = ((x1 - x0) * (y1 - y0)) as f32;
+
+    if let Some(limit) = clip_limit {
+        let ceiling = (limit * total / BINS as f32).max(1.0);
+        let mut excess = 0.0;
+        for count in hist.iter_mut() {
+            if *count > ceiling {
+                excess += *count - ceiling;
+                *count = ceiling;
+            }
+        }
+        let share = excess / BINS as f32;
+        for count in hist.iter_mut() {
+            *count += share;
+        }
+    }
+
+    let mut mapping = [0.0f32; BINS];
+    let mut cdf = 0.0;
+    for (m, count) in mapping.iter_mut().zip(&hist) {
+        cdf += count;
+        *m = cdf * 255.0 / total;
+    }
+    mapping
+}
+
+// 在两个相邻桶之间线性插值，避免浮点缓冲区被量化成256级
+fn lookup(mapping: &[f32; BINS], v: f32) -> f32 {
+    let v = v.clamp(0.0, (BINS - 1) as f32);
+    let lo = v.floor() as usize;
+    let hi = (lo + 1).min(BINS - 1);
+    let t = v - lo as f32;
+    mapping[lo] + (mapping[hi] - mapping[lo]) * t
+}
+
+// 自适应直方图均衡：把图片分成 tiles×tiles 块分别均衡，像素值在相邻四块的映射之间双线性插值
+// clip_limit 为 None 时不限制对比度
+pub fn equalize_local(
+    pixels: &[f32],
+    width: u32,
+    height: u32,
+    tiles: u32,
+    clip_limit: Option<f32>,
+) -> Vec<f32> {
+    let w = width as usize;
+    let h = height as usize;
+    let tiles_x = (tiles as usize).clamp(1, w);
+    let tiles_y = (tiles as usize).clamp(1, h);
+
+    let span = |i: usize, n: usize, len: usize| (i * len / n, (i + 1) * len / n);
+    let mappings: Vec<[f32; BINS]> = (0..tiles_x * tiles_y)
+        .into_par_iter()
+        .map(|t| {
+            let (tx, ty) = (t % tiles_x, t / tiles_x);
+            tile_mapping(
+                pixels,
+                w,
+                span(tx, tiles_x, w),
+                span(ty, tiles_y, h),
+                clip_limit,
+            )
+        })
+        .collect();
+
+    // 像素相对分块中心的位置：返回相邻两块的下标与插值权重
+    let neighbours = |p: usize, n: usize, len: usize| {
+        let pos = (p as f32 + 0.5) * n as f32 / len as f32 - 0.5;
+        let lo = pos.floor().clamp(0.0, (n - 1) as f32) as usize;
+        let hi = (lo + 1).min(n - 1);
+        let t = (pos - lo as f32).clamp(0.0, 1.0);
+        (lo, hi, t)
+    };
+
+    let mut output = vec![0.0f32; w * h];
+    output.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        let (ty0, ty1, fy) = neighbours(y, tiles_y, h);
+        for (x, out) in row.iter_mut().enumerate() {
+            let (tx0, tx1, fx) = neighbours(x, tiles_x, w);
+            let v = pixels[y * w + x];
+            let top = lookup(&mappings[ty0 * tiles_x + tx0], v) * (1.0 - fx)
+                + lookup(&mappings[ty0 * tiles_x + tx1], v) * fx;
+            let bottom = lookup(&mappings[ty1 * tiles_x + tx0], v) * (1.0 - fx)
+                + lookup(&mappings[ty1 * tiles_x + tx1], v) * fx;
+            *out = top * (1.0 - fy) + bottom * fy;
+        }
+    });
+
+    output
+}
+
+// 取直方图的百分位数（0-1）
+pub fn percentile(pixels: &[f32], p: f32) -> f32 {
+    let mut hist = [0usize; BINS];
+    for &v in pixels {
+        hist[bin(v)] += 1;
+    }
+    let target = (p.clamp(0.0, 1.0) * pixels.len() as f32).ceil() as usize;
+    let mut cumulative = 0;
+    for (i, &count) in hist.iter().enumerate() {
+        cumulative += count;
+        if cumulative >= target.max(1) {
+            return i as f32;
+        }
+    }
+    (BINS - 1) as f32
+}
+
+// 自动色阶：以 1% 分位作为黑点、中位数（纸面/平坦区域）作为白点，换算为 (light_cut, dark_cut)
+pub fn auto_levels(pixels: &[f32]) -> (f32, f32) {
+    let black = percentile(pixels, 0.01);
+    let white = percentile(pixels, 0.5);
+    let white = white.max(black + 1.0);
+    (255.0 - white, black)
+}
+
+// 自动截断：保持 light_cut/dark_cut 之间的过渡带宽度 band 不变，平移过渡带，
+// 使截断后暗于128（墨迹）的像素约占 coverage（0-1），返回取整后的 (light_cut, dark_cut)
+pub fn auto_cut(pixels: &[f32], coverage: f32, band: f32) -> (f32, f32) {
+    if pixels.is_empty() {
+        return (255.0 - band, 0.0);
+    }
+
+    let mut values = pixels.to_vec();
+    let index = ((coverage.clamp(0.0, 1.0) * values.len() as f32) as usize).min(values.len() - 1);
+    let (_, &mut threshold, _) = values.select_nth_unstable_by(index, f32::total_cmp);
+
+    // 截断后 128 对应的原值为 dark_cut + band·128/255
+    let band = band.clamp(1.0, 255.0);
+    let dark_cut = (threshold - band * 128.0 / 255.0)
+        .round()
+        .clamp(0.0, 255.0 - band);
+    let light_cut = (255.0 - band - dark_cut).round();
+    (light_cut, dark_cut)
+}
+
+// 墨迹覆盖率：暗于128的像素比例
+pub fn ink_coverage(pixels: &[f32]) -> f32 {
+    if pixels.is_empty() {
+        return 0.0;
+    }
+    pixels.iter().filter(|&&v| v < 128.0).count() as f32 / pixels.len() as f32
+}
@@ -0,0 +1,395 @@
+use ndarray::Array2;
+use rayon::prelude::*;
+
+use crate::error::OLIError;
+
+// 使用ndarray进行卷积运算 - 均值核与可分离核自动走快速路径
+// 输入输出均为浮点缓冲区，结果不截断（Sobel等带符号的响应保留负值）
+pub fn convolve_ndarray(pixels: &[f32], width: u32, height: u32, kernel: &Array2<f32>) -> Vec<f32> {
+    if is_box_kernel(kernel) {
+        let (kh, kw) = kernel.dim();
+        return box_blur(pixels, width, height, kw, kh);
+    }
+
+    if let Some((col, row)) = separate_kernel(kernel) {
+        return convolve_separable(pixels, width, height, &col, &row);
+    }
+
+    convolve_2d(pixels, width, height, kernel)
+}
+
+// 完整二维卷积（并行优化版本），每个像素 O(k²)
+fn convolve_2d(pixels: &[f32], width: u32, height: u32, kernel: &Array2<f32>) -> Vec<f32> {
+    let (kh, kw) = kernel.dim();
+    let half_h = kh / 2;
+    let half_w = kw / 2;
+
+    // 使用并行迭代器处理每一行
+    let output: Vec<f32> = (0..height)
+        .into_par_iter()
+        .flat_map(|y| {
+            let mut row = vec![0.0f32; width as usize];
+            for x in 0..width {
+                let mut sum = 0.0;
+                for ky in 0..kh {
+                    for kx in 0..kw {
+                        let py = (y as i32 + ky as i32 - half_h as i32).clamp(0, height as i32 - 1)
+                            as u32;
+                        let px = (x as i32 + kx as i32 - half_w as i32).clamp(0, width as i32 - 1)
+                            as u32;
+                        let idx = (py * width + px) as usize;
+                        sum += pixels[idx] * kernel[[ky, kx]];
+                    }
+                }
+                row[x as usize] = sum;
+            }
+            row
+        })
+        .collect();
+
+    output
+}
+
+// 判断是否为归一化的均值（box）卷积核
+fn is_box_kernel(kernel: &Array2<f32>) -> bool {
+    let (kh, kw) = kernel.dim();
+    let first = kernel[[0, 0]];
+    kernel.iter().all(|&v| v == first) && (first * (kh * kw) as f32 - 1.0).abs() < 1e-4
+}
+
+// 尝试把卷积核分解为列向量与行向量的外积（秩为1），Gaussian与Sobel都满足
+fn separate_kernel(kernel: &Array2<f32>) -> Option<(Vec<f32>, Vec<f32>)> {
+    let (kh, kw) = kernel.dim();
+
+    // 以绝对值最大的元素作为分解基准
+    let mut pivot = (0, 0);
+    for ((i, j), &v) in kernel.indexed_iter() {
+        if v.abs() > kernel[pivot].abs() {
+            pivot = (i, j);
+        }
+    }
+    let pivot_val = kernel[pivot];
+    if pivot_val == 0.0 {
+        return None;
+    }
+
+    let col: Vec<f32> = (0..kh).map(|i| kernel[[i, pivot.1]]).collect();
+    let row: Vec<f32> = (0..kw).map(|j| kernel[[pivot.0, j]] / pivot_val).collect();
+
+    let tolerance = 1e-6 * pivot_val.abs().max(1.0);
+    let separable = kernel
+        .indexed_iter()
+        .all(|((i, j), &v)| (col[i] * row[j] - v).abs() <= tolerance);
+
+    separable.then_some((col, row))
+}
+
+// 可分离卷积：先水平后垂直两次一维卷积，每个像素 O(2k)
+fn convolve_separable(
+    pixels: &[f32],
+    width: u32,
+    height: u32,
+    col: &[f32],
+    row: &[f32],
+) -> Vec<f32> {
+    let w = width as usize;
+    let h = height as usize;
+    let half_w = (row.len() / 2) as i32;
+    let half_h = (col.len() / 2) as i32;
+
+    // 水平方向
+    let mut horizontal = vec![0.0f32; w * h];
+    horizontal
+        .par_chunks_mut(w)
+        .enumerate()
+        .for_each(|(y, out_row)| {
+            let src = &pixels[y * w..(y + 1) * w];
+            for (x, out) in out_row.iter_mut().enumerate() {
+                let mut sum = 0.0;
+                for (k, &kv) in row.iter().enumerate() {
+                    let px = (x as i32 + k as i32 - half_w).clamp(0, w as i32 - 1) as usize;
+                    sum += src[px] * kv;
+                }
+                *out = sum;
+            }
+        });
+
+    // 垂直方向
+    let mut output = vec![0.0f32; w * h];
+    output
+        .par_chunks_mut(w)
+        .enumerate()
+        .for_each(|(y, out_row)| {
+            for (x, out) in out_row.iter_mut().enumerate() {
+                let mut sum = 0.0;
+                for (k, &kv) in col.iter().enumerate() {
+                    let py = (y as i32 + k as i32 - half_h).clamp(0, h as i32 - 1) as usize;
+                    sum += horizontal[py * w + x] * kv;
+                }
+                *out = sum;
+            }
+        });
+
+    output
+}
+
+// 均值模糊：滑动窗口累加，每个像素 O(1)，与卷积核大小无关
+fn box_blur(pixels: &[f32], width: u32, height: u32, kw: usize, kh: usize) -> Vec<f32> {
+    let w = width as usize;
+    let h = height as usize;
+    let half_w = (kw / 2) as i32;
+    let half_h = (kh / 2) as i32;
+    let clamp_x = |x: i32| x.clamp(0, w as i32 - 1) as usize;
+    let clamp_y = |y: i32| y.clamp(0, h as i32 - 1) as usize;
+
+    // 水平方向窗口和（双精度累加，避免滑动过程中的误差累积）
+    let mut horizontal = vec![0.0f64; w * h];
+    horizontal
+        .par_chunks_mut(w)
+        .enumerate()
+        .for_each(|(y, out_row)| {
+            let src = &pixels[y * w..(y + 1) * w];
+            let mut sum: f64 = (-half_w..-half_w + kw as i32)
+                .map(|x| src[clamp_x(x)] as f64)
+                .sum();
+            for (x, out) in out_row.iter_mut().enumerate() {
+                *out = sum;
+                let x = x as i32;
+                sum = sum + src[clamp_x(x + kw as i32 - half_w)] as f64
+                    - src[clamp_x(x - half_w)] as f64;
+            }
+        });
+
+    // 垂直方向按行带并行，每个行带内滑动累加
+    const BAND: usize = 64;
+    let area = (kw * kh) as f64;
+    let mut output = vec![0.0f32; w * h];
+    output
+        .par_chunks_mut(w * BAND)
+        .enumerate()
+        .for_each(|(band, out_band)| {
+            let y0 = band * BAND;
+            let mut sums = vec![0.0f64; w];
+            for y in -half_h..-half_h + kh as i32 {
+                let src = &horizontal[clamp_y(y0 as i32 + y) * w..][..w];
+                for (s, &v) in sums.iter_mut().zip(src) {
+                    *s += v;
+                }
+            }
+
+            for (dy, out_row) in out_band.chunks_mut(w).enumerate() {
+                for (out, &s) in out_row.iter_mut().zip(&sums) {
+                    *out = (s / area) as f32;
+                }
+                let y = (y0 + dy) as i32;
+                let add = &horizontal[clamp_y(y + kh as i32 - half_h) * w..][..w];
+                let sub = &horizontal[clamp_y(y - half_h) * w..][..w];
+                for ((s, &a), &b) in sums.iter_mut().zip(add).zip(sub) {
+                    *s = *s + a - b;
+                }
+            }
+        });
+
+    output
+}
+
+// 卷积核形状
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KernelShape {
+    Box,
+    Gaussian,
+    Disk,
+}
+
+impl KernelShape {
+    pub fn from_name(name: &str) -> Result<Self, OLIError> {
+        match name {
+            "box" => Ok(KernelShape::Box),
+            "gaussian" => Ok(KernelShape::Gaussian),
+            "disk" => Ok(KernelShape::Disk),
+            _ => Err(OLIError::InvalidConfig(format!(
+                "unknown kernel shape \"{name}\""
+            ))),
+        }
+    }
+
+    // 生成半径为 radius（可为小数）的归一化卷积核
+    // 高斯核的sigma取 radius·2/3，与同半径均值核的标准差接近，覆盖 ±3σ
+    pub fn build(self, radius: f32) -> Array2<f32> {
+        match self {
+            KernelShape::Box => box_kernel(radius),
+            KernelShape::Gaussian => {
+                let sigma = (radius * 2.0 / 3.0).max(0.1);
+                let half = (sigma * 3.0).ceil() as usize;
+                gaussian_kernel_ndarray(2 * half + 1, sigma)
+            }
+            KernelShape::Disk => disk_kernel(radius),
+        }
+    }
+}
+
+// 均值卷积核：整数半径时为 (2r+1)×(2r+1) 的等权核，小数部分作为最外圈的部分权重
+pub fn box_kernel(radius: f32) -> Array2<f32> {
+    let whole = radius.floor() as usize;
+    let frac = radius - whole as f32;
+
+    if frac < 1e-6 {
+        let size = 2 * whole + 1;
+        return Array2::from_elem((size, size), 1.0 / (size * size) as f32);
+    }
+
+    let size = 2 * whole + 3;
+    let mut weights = vec![1.0f32; size];
+    weights[0] = frac;
+    weights[size - 1] = frac;
+    let total: f32 = weights.iter().sum();
+
+    Array2::from_shape_fn((size, size), |(y, x)| {
+        weights[y] * weights[x] / (total * total)
+    })
+}
+
+// 圆盘卷积核：与圆心距离不超过 radius 的位置等权
+pub fn disk_kernel(radius: f32) -> Array2<f32> {
+    let half = radius.floor() as i32;
+    let size = (2 * half + 1) as usize;
+    let kernel = Array2::from_shape_fn((size, size), |(y, x)| {
+        let dx = x as i32 - half;
+        let dy = y as i32 - half;
+        if (dx * dx + dy * dy) as f32 <= radius * radius {
+            1.0
+        } else {
+            0.0
+        }
+    });
+    let sum = kernel.sum();
+    kernel / sum
+}
+
+// 使用ndarray生成高斯卷积核
+pub fn gaussian_kernel_ndarray(size: usize, sigma: f32) -> Array2<f32> {
+    let half = (size / 2) as i32;
+    let mut kernel = Array2::zeros((size, size));
+    let mut sum = 0.0;
+
+    for y in 0..size {
+        for x in 0..size {
+            let dx = x as i32 - half;
+            let dy = y as i32 - half;
+            let value = (-((dx * dx + dy * dy) as f32) / (2.0 * sigma * sigma)).exp();
+            kernel[[y, x]] = value;
+            sum += value;
+        }
+    }
+
+    // 归一化
+    kernel / sum
+}
+
+// 动态生成Sobel算子 - Sobel = 高斯平滑 ⊗ 差分
+// Sobel算子是[1,2,1]^T（高斯平滑）和[-1,0,1]（差分）的外积
+fn sobel_x_kernel() -> Array2<f32> {
+    // 高斯平滑向量（垂直方向）
+    let smooth = Array2::from_shape_vec((3, 1), vec![1.0, 2.0, 1.0]).unwrap();
+    // 差分向量（水平方向）
+    let diff = Array2::from_shape_vec((1, 3), vec![-1.0, 0.0, 1.0]).unwrap();
+
+    // 外积生成Sobel X算子
+    let mut kernel = Array2::zeros((3, 3));
+    for i in 0..3 {
+        for j in 0..3 {
+            kernel[[i, j]] = smooth[[i, 0]] * diff[[0, j]];
+        }
+    }
+    kernel
+}
+
+fn sobel_y_kernel() -> Array2<f32> {
+    // 差分向量（垂直方向）
+    let diff = Array2::from_shape_vec((3, 1), vec![-1.0, 0.0, 1.0]).unwrap();
+    // 高斯平滑向量（水平方向）
+    let smooth = Array2::from_shape_vec((1, 3), vec![1.0, 2.0, 1.0]).unwrap();
+
+    // 外积生成Sobel Y算子
+    let mut kernel = Array2::zeros((3, 3));
+    for i in 0..3 {
+        for j in 0..3 {
+            kernel[[i, j]] = diff[[i, 0]] * smooth[[0, j]];
+        }
+    }
+    kernel
+}
+
+// 带符号的Sobel梯度 (gx, gy)
+// 正值表示沿坐标增加方向由暗变亮，负值表示由亮变暗，不做截断
+pub fn sobel_gradients(pixels: &[f32], width: u32, height: u32) -> (Vec<f32>, Vec<f32>) {
+    let gx = convolve_ndarray(pixels, width, height, &sobel_x_kernel());
+    let gy = convolve_ndarray(pixels, width, height, &sobel_y_kernel());
+    (gx, gy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 固定种子的伪随机缓冲区（0-255），不引入额外依赖
+    fn noise(width: u32, height: u32) -> Vec<f32> {
+        let mut state = 0x2545_f491_u32;
+        (0..width * height)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state % 256) as f32
+            })
+            .collect()
+    }
+
+    // 快速路径与完整二维卷积逐像素比较，包括边缘的行与列
+    fn assert_matches_2d(width: u32, height: u32, kernel: &Array2<f32>) {
+        let pixels = noise(width, height);
+        let fast = convolve_ndarray(&pixels, width, height, kernel);
+        let naive = convolve_2d(&pixels, width, height, kernel);
+        for (i, (a, b)) in fast.iter().zip(&naive).enumerate() {
+            assert!(
+                (a - b).abs() < 1e-2,
+                "{:?} kernel differs at ({}, {}): {a} vs {b}",
+                kernel.dim(),
+                i as u32 % width,
+                i as u32 / width
+            );
+        }
+    }
+
+    #[test]
+    fn box_fast_path_matches_2d() {
+        for radius in [1.0, 2.0, 6.0] {
+            let kernel = box_kernel(radius);
+            assert!(is_box_kernel(&kernel));
+            assert_matches_2d(37, 23, &kernel);
+        }
+        // 卷积核比图片还大时边缘像素被重复使用
+        assert_matches_2d(9, 7, &box_kernel(8.0));
+        // 非正方形的均值核
+        assert_matches_2d(37, 23, &Array2::from_elem((3, 7), 1.0 / 21.0));
+    }
+
+    #[test]
+    fn separable_fast_path_matches_2d() {
+        for kernel in [
+            gaussian_kernel_ndarray(7, 1.5),
+            box_kernel(2.5),
+            sobel_x_kernel(),
+            sobel_y_kernel(),
+        ] {
+            assert!(separate_kernel(&kernel).is_some());
+            assert_matches_2d(37, 23, &kernel);
+        }
+        assert_matches_2d(5, 4, &gaussian_kernel_ndarray(13, 3.0));
+    }
+
+    #[test]
+    fn disk_kernel_is_not_separable() {
+        assert!(separate_kernel(&disk_kernel(3.0)).is_none());
+    }
+}
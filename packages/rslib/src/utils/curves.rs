@@ -0,0 +1,146 @@
+use crate::CurveConfig;
+use crate::error::OLIError;
+
+// 色调曲线：先按黑白场与gamma做色阶映射，再按控制点的单调三次样条映射
+pub struct Curve {
+    black: f32,
+    white: f32,
+    gamma: f32,
+    spline: Option<MonotoneSpline>,
+}
+
+impl Curve {
+    pub fn from_config(config: &CurveConfig) -> Result<Self, OLIError> {
+        let black = config.black_point.unwrap_or(0.0);
+        let white = config.white_point.unwrap_or(255.0);
+        let gamma = config.gamma.unwrap_or(1.0);
+
+        if white <= black {
+            return Err(OLIError::InvalidConfig(format!(
+                "curve white_point ({white}) must be greater than black_point ({black})"
+            )));
+        }
+        if gamma.is_nan() || gamma <= 0.0 {
+            return Err(OLIError::InvalidConfig(format!(
+                "curve gamma must be positive, got {gamma}"
+            )));
+        }
+
+        let spline = match &config.points {
+            Some(points) => Some(MonotoneSpline::new(points)?),
+            None => None,
+        };
+
+        Ok(Curve {
+            black,
+            white,
+            gamma,
+            spline,
+        })
+    }
+
+    pub fn apply(&self, v: f32) -> f32 {
+        let t = ((v - self.black) / (self.white - self.black)).clamp(0.0, 1.0);
+        let v = 255.0 * t.powf(1.0 / self.gamma);
+        match &self.spline {
+            Some(spline) => spline.eval(v),
+            None => v,
+        }
+    }
+
+    pub fn apply_all(&self, pixels: &mut [f32]) {
+        for v in pixels.iter_mut() {
+            *v = self.apply(*v);
+        }
+    }
+}
+
+// 单调三次Hermite样条（Fritsch-Carlson），控制点单调时曲线也单调，不会出现过冲
+struct MonotoneSpline {
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+    tangents: Vec<f32>,
+}
+
+impl MonotoneSpline {
+    fn new(points: &[[f32; 2]]) -> Result<Self, OLIError> {
+        if points.len() < 2 {
+            return Err(OLIError::InvalidConfig(
+                "curve needs at least 2 control points".to_string(),
+            ));
+        }
+
+        let mut points = points.to_vec();
+        points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        if points.windows(2).any(|p| p[1][0] <= p[0][0]) {
+            return Err(OLIError::InvalidConfig(
+                "curve control points must have distinct x values".to_string(),
+            ));
+        }
+
+        let xs: Vec<f32> = points.iter().map(|p| p[0]).collect();
+        let ys: Vec<f32> = points.iter().map(|p| p[1]).collect();
+        let n = xs.len();
+
+        let secants: Vec<f32> = (0..n - 1)
+            .map(|k| (ys[k + 1] - ys[k]) / (xs[k + 1] - xs[k]))
+            .collect();
+
+        let mut tangents = vec![0.0f32; n];
+        tangents[0] = secants[0];
+        tangents[n - 1] = secants[n - 2];
+        for k in 1..n - 1 {
+            tangents[k] = if secants[k - 1] * secants[k] <= 0.0 {
+                0.0
+            } else {
+                (secants[k - 1] + secants[k]) / 2.0
+            };
+        }
+
+        // 限制切线长度，保证每一段单调
+        for k in 0..n - 1 {
+            if secants[k] == 0.0 {
+                tangents[k] = 0.0;
+                tangents[k + 1] = 0.0;
+                continue;
+            }
+            let a = tangents[k] / secants[k];
+            let b = tangents[k + 1] / secants[k];
+            let norm = a * a + b * b;
+            if norm > 9.0 {
+                let tau = 3.0 / norm.sqrt();
+                tangents[k] = tau * a * secants[k];
+                tangents[k + 1] = tau * b * secants[k];
+            }
+        }
+
+        Ok(MonotoneSpline { xs, ys, tangents })
+    }
+
+    // 控制点范围之外取端点值
+    fn eval(&self, x: f32) -> f32 {
+        let n = self.xs.len();
+        if x <= self.xs[0] {
+            return self.ys[0];
+        }
+        if x >= self.xs[n - 1] {
+            return self.ys[n - 1];
+        }
+
+        let k = self.xs.partition_point(|&xk| xk <= x) - 1;
+        let h = self.xs[k + 1] - self.xs[k];
+        let t = (x - self.xs[k]) / h;
+        let t2 = t * t;
+        let t3 = t2 * t;
+
+        let h00 = 2.0 * t3 - 3.0 * t2 + 1.0;
+        let h10 = t3 - 2.0 * t2 + t;
+        let h01 = -2.0 * t3 + 3.0 * t2;
+        let h11 = t3 - t2;
+
+        h00 * self.ys[k]
+            + h10 * h * self.tangents[k]
+            + h01 * self.ys[k + 1]
+            + h11 * h * self.tangents[k + 1]
+    }
+}
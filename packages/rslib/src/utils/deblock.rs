@@ -0,0 +1,246 @@
+use image::DynamicImage;
+use rayon::prelude::*;
+
+use super::denoise::bilateral;
+
+// JPEG块大小
+const BLOCK: usize = 8;
+
+// JPEG标准亮度量化表（质量50），用于估算压缩质量
+const STD_LUMINANCE_TABLE: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113,
+    92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+// 强制去块但无法读取量化表时使用的量化步长（质量50的DC量化值）
+pub const DEFAULT_DC_STEP: u16 = 16;
+
+// 网格对齐检测：块边界处的平均梯度至少要比其他位置高出这个比例
+const GRID_CONTRAST: f32 = 1.15;
+
+// 从JPEG文件中读取的量化信息
+pub struct JpegQuality {
+    // 估算的压缩质量（1-100，与libjpeg的quality参数对应）
+    pub quality: u32,
+    // 亮度DC量化步长
+    pub dc_step: u16,
+}
+
+// 解析JPEG的亮度量化表并估算压缩质量，不是JPEG或没有量化表时返回None
+pub fn estimate_jpeg_quality(input: &[u8]) -> Option<JpegQuality> {
+    if input.len() < 4 || input[0] != 0xFF || input[1] != 0xD8 {
+        return None;
+    }
+
+    let mut pos = 2;
+    while pos + 4 <= input.len() {
+        if input[pos] != 0xFF {
+            return None;
+        }
+        let marker = input[pos + 1];
+        // 填充字节
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        // 扫描开始，量化表只会出现在之前
+        if marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+
+        let length = u16::from_be_bytes([input[pos + 2], input[pos + 3]]) as usize;
+        let end = (pos + 2 + length).min(input.len());
+
+        if marker == 0xDB {
+            let mut p = pos + 4;
+            while p < end {
+                let precision = input[p] >> 4;
+                let id = input[p] & 0x0F;
+                let entry = if precision == 0 { 1 } else { 2 };
+                let table_end = p + 1 + 64 * entry;
+                if table_end > end {
+                    return None;
+                }
+
+                if id == 0 {
+                    let table: Vec<u16> = (0..64)
+                        .map(|i| {
+                            let o = p + 1 + i * entry;
+                            if entry == 1 {
+                                input[o] as u16
+                            } else {
+                                u16::from_be_bytes([input[o], input[o + 1]])
+                            }
+                        })
+                        .collect();
+                    return Some(quality_from_table(&table));
+                }
+                p = table_end;
+            }
+        }
+
+        pos = end;
+    }
+
+    None
+}
+
+// libjpeg按 scale% 缩放标准表，由量化值之和反推 scale 和 quality
+fn quality_from_table(table: &[u16]) -> JpegQuality {
+    let sum: u32 = table.iter().map(|&q| q as u32).sum();
+    let std_sum: u32 = STD_LUMINANCE_TABLE.iter().map(|&q| q as u32).sum();
+    let scale = (sum as f32 * 100.0 / std_sum as f32).max(1.0);
+    let quality = if scale <= 100.0 {
+        (200.0 - scale) / 2.0
+    } else {
+        5000.0 / scale
+    };
+
+    JpegQuality {
+        quality: quality.round().clamp(1.0, 100.0) as u32,
+        // 量化表按zigzag顺序存储，第一项即DC
+        dc_step: table[0].max(1),
+    }
+}
+
+// 检测8×8块网格在某个方向上的偏移：块边界两侧的平均差值明显大于其他位置
+// horizontal 为 true 时检测竖直的块边界（沿x方向的偏移）
+fn detect_grid_offset(
+    luma: &[f32],
+    width: usize,
+    height: usize,
+    horizontal: bool,
+) -> Option<usize> {
+    let (len, lines) = if horizontal {
+        (width, height)
+    } else {
+        (height, width)
+    };
+    if len < 2 * BLOCK {
+        return None;
+    }
+
+    let mut sums = [0.0f64; BLOCK];
+    let mut counts = [0u64; BLOCK];
+    for line in 0..lines {
+        for i in 1..len {
+            let (a, b) = if horizontal {
+                (luma[line * width + i - 1], luma[line * width + i])
+            } else {
+                (luma[(i - 1) * width + line], luma[i * width + line])
+            };
+            sums[i % BLOCK] += (a - b).abs() as f64;
+            counts[i % BLOCK] += 1;
+        }
+    }
+
+    let means: Vec<f32> = sums
+        .iter()
+        .zip(&counts)
+        .map(|(&s, &c)| (s / c.max(1) as f64) as f32)
+        .collect();
+    let (offset, &best) = means.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1))?;
+    let others = (means.iter().sum::<f32>() - best) / (BLOCK - 1) as f32;
+
+    (best > others * GRID_CONTRAST).then_some(offset)
+}
+
+// 平滑块边界：边界两侧亮度差小于 alpha、两侧各自平坦（差值小于 beta）时，
+// 认为是量化造成的台阶而不是真实边缘，把差值按距离线性分摊到两侧各3个像素
+fn filter_boundaries(
+    channel: &mut [f32],
+    width: usize,
+    height: usize,
+    offset: usize,
+    horizontal: bool,
+    alpha: f32,
+    beta: f32,
+) {
+    let (len, lines) = if horizontal {
+        (width, height)
+    } else {
+        (height, width)
+    };
+    let index = |line: usize, i: usize| {
+        if horizontal {
+            line * width + i
+        } else {
+            i * width + line
+        }
+    };
+
+    let mut edge = offset;
+    while edge < 3 {
+        edge += BLOCK;
+    }
+    while edge + 3 <= len {
+        for line in 0..lines {
+            let p = [
+                channel[index(line, edge - 1)],
+                channel[index(line, edge - 2)],
+                channel[index(line, edge - 3)],
+            ];
+            let q = [
+                channel[index(line, edge)],
+                channel[index(line, edge + 1)],
+                channel[index(line, edge + 2)],
+            ];
+
+            let d = q[0] - p[0];
+            if d.abs() >= alpha || (p[0] - p[1]).abs() >= beta || (q[0] - q[1]).abs() >= beta {
+                continue;
+            }
+
+            for k in 0..3 {
+                let amount = d * (3 - k) as f32 / 8.0;
+                channel[index(line, edge - 1 - k)] = p[k] + amount;
+                channel[index(line, edge + k)] = q[k] - amount;
+            }
+        }
+        edge += BLOCK;
+    }
+}
+
+// 去块与去振铃：检测块网格对齐后平滑块边界，再用双边滤波抑制边缘附近的振铃
+// dc_step 为量化步长，决定允许平滑的台阶大小；检测不到块网格时只做去振铃
+pub fn deblock(img: &DynamicImage, dc_step: u16) -> DynamicImage {
+    let mut rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let w = width as usize;
+    let h = height as usize;
+
+    let luma: Vec<f32> = rgba
+        .pixels()
+        .map(|p| p[0] as f32 * 0.299 + p[1] as f32 * 0.587 + p[2] as f32 * 0.114)
+        .collect();
+    let offset_x = detect_grid_offset(&luma, w, h, true);
+    let offset_y = detect_grid_offset(&luma, w, h, false);
+
+    let step = dc_step as f32;
+    let alpha = 2.0 * step;
+    let beta = 0.5 * step;
+
+    let channels: Vec<Vec<f32>> = (0..3)
+        .into_par_iter()
+        .map(|c| {
+            let mut channel: Vec<f32> = rgba.pixels().map(|p| p[c] as f32).collect();
+            if let Some(offset) = offset_x {
+                filter_boundaries(&mut channel, w, h, offset, true, alpha, beta);
+            }
+            if let Some(offset) = offset_y {
+                filter_boundaries(&mut channel, w, h, offset, false, alpha, beta);
+            }
+            // 去振铃：亮度差在量化步长量级以内的波纹被平滑，更大的真实边缘保留
+            bilateral(&channel, width, height, 1.0, step)
+        })
+        .collect();
+
+    for (i, pixel) in rgba.pixels_mut().enumerate() {
+        for (c, channel) in channels.iter().enumerate() {
+            pixel[c] = channel[i].round().clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    DynamicImage::ImageRgba8(rgba)
+}
@@ -0,0 +1,74 @@
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+
+use super::image_processing::colorize_text;
+use super::text::{TextAlign, bundled_font, rasterize_text};
+use super::watermark::blend;
+
+// 拼图中每格缩略图的最大边长
+const TILE_SIZE: u32 = 320;
+// 格子之间的间距
+const TILE_GAP: u32 = 8;
+// 名称标签的字号、占用的高度与距缩略图的距离
+const LABEL_SIZE: f32 = 16.0;
+const LABEL_HEIGHT: u32 = 24;
+const LABEL_MARGIN: u32 = 4;
+// 拼图背景色，透明的中间结果在上面也能看清
+const BACKGROUND: Rgba<u8> = Rgba([224, 224, 224, 255]);
+
+// 按比例缩小到 TILE_SIZE 以内，调试模式记录中间结果时使用，避免每个阶段都保留一份完整大小的图片
+pub fn thumbnail(image: &RgbaImage) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    if w == 0 || h == 0 {
+        return image.clone();
+    }
+    let scale = (TILE_SIZE as f32 / w.max(h) as f32).min(1.0);
+    let tw = ((w as f32 * scale).round() as u32).max(1);
+    let th = ((h as f32 * scale).round() as u32).max(1);
+    if (tw, th) == (w, h) {
+        image.clone()
+    } else {
+        imageops::resize(image, tw, th, FilterType::Triangle)
+    }
+}
+
+// 把各阶段的中间结果拼成一张图：每格按比例缩小到 TILE_SIZE 以内，下方标注名称
+// 未启用 bundled-font 特性时不绘制名称
+pub fn contact_sheet(snapshots: &[(String, RgbaImage)]) -> RgbaImage {
+    let count = snapshots.len().max(1) as u32;
+    let cols = (count as f32).sqrt().ceil() as u32;
+    let rows = count.div_ceil(cols);
+    let cell_w = TILE_SIZE + TILE_GAP;
+    let cell_h = TILE_SIZE + LABEL_HEIGHT + TILE_GAP;
+    let mut sheet = RgbaImage::from_pixel(
+        cols * cell_w + TILE_GAP,
+        rows * cell_h + TILE_GAP,
+        BACKGROUND,
+    );
+
+    for (i, (name, image)) in snapshots.iter().enumerate() {
+        let x = TILE_GAP + (i as u32 % cols) * cell_w;
+        let y = TILE_GAP + (i as u32 / cols) * cell_h;
+
+        if image.width() > 0 && image.height() > 0 {
+            let thumb = thumbnail(image);
+            let (tw, th) = thumb.dimensions();
+            let tx = x + (TILE_SIZE - tw) / 2;
+            let ty = y + (TILE_SIZE - th) / 2;
+            blend(&mut sheet, &thumb, tx as i64, ty as i64, 1.0);
+        }
+
+        if let Some(font) = bundled_font() {
+            let label = colorize_text(
+                &rasterize_text(font, name, LABEL_SIZE, TextAlign::Left),
+                false,
+            );
+            let label =
+                imageops::crop_imm(&label, 0, 0, TILE_SIZE, LABEL_HEIGHT - LABEL_MARGIN).to_image();
+            let ly = y + TILE_SIZE + LABEL_MARGIN;
+            blend(&mut sheet, &label, x as i64, ly as i64, 1.0);
+        }
+    }
+
+    sheet
+}
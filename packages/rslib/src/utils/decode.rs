@@ -0,0 +1,371 @@
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits};
+use serde::Serialize;
+
+use crate::OLIConfig;
+use crate::error::OLIError;
+
+// 默认解码限制 - 要能处理手机照片（12MP 左右）与聊天中的长截图（如 1080x12000），
+// 超出时直接报错而不是在wasm里OOM
+const DEFAULT_MAX_DIMENSION: u32 = 16384;
+const DEFAULT_MAX_ALLOC: u64 = 512 * 1024 * 1024;
+
+// 处理流程中每个像素大约占用的字节数（缩放后的RGBA、彩色层、合成结果以及若干灰度缓冲区）
+// 默认流程处理 12MP 照片时实测峰值约为每像素 27 字节
+const WORKING_BYTES_PER_PIXEL: u64 = 32;
+
+// 解码限制
+#[derive(Clone)]
+pub struct DecodeLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_alloc: u64,
+}
+
+impl DecodeLimits {
+    pub fn from_config(config: Option<&OLIConfig>) -> Self {
+        DecodeLimits {
+            max_width: config
+                .and_then(|c| c.max_width)
+                .unwrap_or(DEFAULT_MAX_DIMENSION),
+            max_height: config
+                .and_then(|c| c.max_height)
+                .unwrap_or(DEFAULT_MAX_DIMENSION),
+            max_alloc: config
+                .and_then(|c| c.max_alloc)
+                .unwrap_or(DEFAULT_MAX_ALLOC),
+        }
+    }
+
+    fn to_image_limits(&self) -> Limits {
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(self.max_width);
+        limits.max_image_height = Some(self.max_height);
+        limits.max_alloc = Some(self.max_alloc);
+        limits
+    }
+
+    // 检查尺寸是否在限制之内
+    pub fn check_dimensions(&self, width: u32, height: u32) -> Result<(), OLIError> {
+        if width > self.max_width || height > self.max_height {
+            return Err(OLIError::LimitsExceeded(format!(
+                "{width}x{height} is larger than {}x{}",
+                self.max_width, self.max_height
+            )));
+        }
+        Ok(())
+    }
+
+    // 检查处理流程的工作缓冲区是否超出内存限制
+    pub fn check_working_set(&self, width: u32, height: u32) -> Result<(), OLIError> {
+        self.check_dimensions(width, height)?;
+        let bytes = width as u64 * height as u64 * WORKING_BYTES_PER_PIXEL;
+        if bytes > self.max_alloc {
+            return Err(OLIError::LimitsExceeded(format!(
+                "processing {width}x{height} needs about {bytes} bytes, limit is {}",
+                self.max_alloc
+            )));
+        }
+        Ok(())
+    }
+}
+
+// 只读取文件头得到的信息
+pub struct HeaderInfo {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+// 预检：只解析文件头，获取格式和尺寸，不解码像素数据
+pub fn probe_header(input: &[u8]) -> Result<HeaderInfo, OLIError> {
+    let reader = ImageReader::new(Cursor::new(input))
+        .with_guessed_format()
+        .map_err(|e| OLIError::Decode(e.to_string()))?;
+    let format = reader.format().ok_or(OLIError::UnsupportedFormat)?;
+    let (width, height) = reader.into_dimensions()?;
+
+    Ok(HeaderInfo {
+        format,
+        width,
+        height,
+    })
+}
+
+// 在限制之内解码图片
+pub fn load_image(input: &[u8], limits: &DecodeLimits) -> Result<DynamicImage, OLIError> {
+    let header = probe_header(input)?;
+    limits.check_dimensions(header.width, header.height)?;
+
+    let mut reader = ImageReader::with_format(Cursor::new(input), header.format);
+    reader.limits(limits.to_image_limits());
+    Ok(reader.decode()?)
+}
+
+// probe返回的图片元信息
+#[derive(Serialize)]
+pub struct ImageInfo {
+    pub format: String,
+    pub width: u32,
+    pub height: u32,
+    pub frame_count: u32,
+    pub color_type: String,
+    pub bit_depth: u16,
+    // EXIF方向（1-8，1表示无旋转）
+    pub orientation: u8,
+    pub has_alpha: bool,
+}
+
+// 读取图片元信息，使用与load_image相同的解码器和限制，但不解码像素数据（动图帧数从容器结构中读取）
+pub fn probe(input: &[u8], limits: &DecodeLimits) -> Result<ImageInfo, OLIError> {
+    let header = probe_header(input)?;
+    limits.check_dimensions(header.width, header.height)?;
+
+    let mut reader = ImageReader::with_format(Cursor::new(input), header.format);
+    reader.limits(limits.to_image_limits());
+    let mut decoder = reader.into_decoder()?;
+
+    let color_type = decoder.original_color_type();
+    let bit_depth = color_type.bits_per_pixel() / color_type.channel_count().max(1) as u16;
+    let has_alpha = decoder.color_type().has_alpha();
+    let orientation = decoder.orientation()?.to_exif();
+    let frame_count = count_frames(input, header.format);
+
+    Ok(ImageInfo {
+        format: format!("{:?}", header.format).to_lowercase(),
+        width: header.width,
+        height: header.height,
+        frame_count,
+        color_type: format!("{color_type:?}"),
+        bit_depth,
+        orientation,
+        has_alpha,
+    })
+}
+
+// 统计动图帧数，静态图片返回1
+// 只遍历容器结构（GIF 的图像描述符、APNG 的 acTL、WebP 的 ANMF 块），不解码任何帧；
+// 文件截断时返回已经数到的帧数
+fn count_frames(input: &[u8], format: ImageFormat) -> u32 {
+    let frames = match format {
+        ImageFormat::Gif => count_gif_frames(input),
+        ImageFormat::Png => apng_frames(input).unwrap_or(1),
+        ImageFormat::WebP => count_webp_frames(input),
+        _ => 1,
+    };
+
+    frames.max(1)
+}
+
+// 颜色表的字节数：packed 的最高位表示存在颜色表，低3位为大小
+fn gif_color_table_len(packed: u8) -> usize {
+    if packed & 0x80 != 0 {
+        3 << ((packed & 0x07) + 1)
+    } else {
+        0
+    }
+}
+
+// 跳过 GIF 的数据子块序列（长度字节 + 数据，以长度0结束），返回之后的位置
+fn skip_gif_sub_blocks(input: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *input.get(pos)? as usize;
+        pos += 1;
+        if len == 0 {
+            return Some(pos);
+        }
+        pos += len;
+    }
+}
+
+// GIF：统计图像描述符（0x2C）的数量
+fn count_gif_frames(input: &[u8]) -> u32 {
+    // 6 字节签名 + 7 字节逻辑屏幕描述符，之后是可选的全局颜色表
+    let Some(&packed) = input.get(10) else {
+        return 1;
+    };
+    let mut pos = 13 + gif_color_table_len(packed);
+    let mut frames = 0;
+    while let Some(&introducer) = input.get(pos) {
+        match introducer {
+            // 图像描述符：9 字节参数、可选的局部颜色表、LZW 最小码长，之后是图像数据子块
+            0x2C => {
+                let Some(&packed) = input.get(pos + 9) else {
+                    break;
+                };
+                frames += 1;
+                pos += 10 + gif_color_table_len(packed) + 1;
+            }
+            // 扩展块：标签之后是数据子块
+            0x21 => pos += 2,
+            // 0x3B 为结束标记，其他字节说明文件已损坏
+            _ => break,
+        }
+        match skip_gif_sub_blocks(input, pos) {
+            Some(next) => pos = next,
+            None => break,
+        }
+    }
+    frames
+}
+
+// APNG：读取 acTL 块中的 num_frames，不是 APNG 时返回 None
+fn apng_frames(input: &[u8]) -> Option<u32> {
+    // 8 字节签名之后依次为“长度、类型、数据、CRC”的块，acTL 必须出现在 IDAT 之前
+    let mut pos = 8;
+    loop {
+        let len = u32::from_be_bytes(input.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        let kind = input.get(pos + 4..pos + 8)?;
+        match kind {
+            b"acTL" => {
+                let data = input.get(pos + 8..pos + 12)?;
+                return Some(u32::from_be_bytes(data.try_into().ok()?));
+            }
+            b"IDAT" | b"IEND" => return None,
+            // 长度可能是任意值，整个和都要检查（wasm32 上 usize 只有32位），超出文件时停止
+            _ => {
+                pos = len
+                    .checked_add(12)
+                    .and_then(|n| pos.checked_add(n))
+                    .filter(|&next| next <= input.len())?
+            }
+        }
+    }
+}
+
+// WebP：统计 ANMF 块的数量，静态图片没有 ANMF 块
+fn count_webp_frames(input: &[u8]) -> u32 {
+    // 12 字节 RIFF 头之后依次为“类型、长度（小端）、数据”的块，数据按偶数字节对齐
+    let mut pos = 12;
+    let mut frames = 0;
+    while let Some(header) = input.get(pos..pos + 8) {
+        if &header[..4] == b"ANMF" {
+            frames += 1;
+        }
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        match len
+            .checked_add(8 + (len & 1))
+            .and_then(|n| pos.checked_add(n))
+            .filter(|&next| next <= input.len())
+        {
+            Some(next) => pos = next,
+            None => break,
+        }
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, Frame, Rgba, RgbaImage};
+
+    use super::*;
+
+    // 块数据：类型、长度与数据，PNG 为大端并带 CRC，WebP 为小端并按偶数字节对齐
+    fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    fn webp_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = kind.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn gif(frames: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut out);
+            for i in 0..frames {
+                let image = RgbaImage::from_pixel(16, 12, Rgba([i as u8 * 30, 0, 0, 255]));
+                encoder
+                    .encode_frame(Frame::from_parts(
+                        image,
+                        0,
+                        0,
+                        Delay::from_numer_denom_ms(50, 1),
+                    ))
+                    .unwrap();
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn counts_gif_frames_without_decoding() {
+        assert_eq!(count_frames(&gif(1), ImageFormat::Gif), 1);
+        assert_eq!(count_frames(&gif(7), ImageFormat::Gif), 7);
+
+        // 截断的文件返回已经数到的帧数
+        let data = gif(7);
+        let truncated = count_frames(&data[..data.len() / 2], ImageFormat::Gif);
+        assert!((1..7).contains(&truncated));
+    }
+
+    #[test]
+    fn reads_apng_frame_count() {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend(png_chunk(b"IHDR", &[0; 13]));
+        let mut apng = png.clone();
+        apng.extend(png_chunk(b"acTL", &[0, 0, 0, 5, 0, 0, 0, 0]));
+        apng.extend(png_chunk(b"IDAT", &[]));
+        png.extend(png_chunk(b"IDAT", &[]));
+
+        assert_eq!(count_frames(&png, ImageFormat::Png), 1);
+        assert_eq!(count_frames(&apng, ImageFormat::Png), 5);
+
+        // 截断的 acTL 与超大的块长度都按静态图片处理
+        assert_eq!(count_frames(&apng[..apng.len() - 26], ImageFormat::Png), 1);
+        let mut hostile = b"\x89PNG\r\n\x1a\n".to_vec();
+        hostile.extend(u32::MAX.to_be_bytes());
+        hostile.extend(b"tEXt");
+        hostile.extend(png_chunk(b"acTL", &[0, 0, 0, 5, 0, 0, 0, 0]));
+        assert_eq!(count_frames(&hostile, ImageFormat::Png), 1);
+    }
+
+    #[test]
+    fn counts_webp_anmf_chunks() {
+        let mut webp = b"RIFF\0\0\0\0WEBP".to_vec();
+        let mut still = webp.clone();
+        still.extend(webp_chunk(b"VP8L", &[0; 5]));
+        webp.extend(webp_chunk(b"VP8X", &[0; 10]));
+        webp.extend(webp_chunk(b"ANIM", &[0; 6]));
+        for _ in 0..3 {
+            webp.extend(webp_chunk(b"ANMF", &[0; 17]));
+        }
+
+        assert_eq!(count_frames(&still, ImageFormat::WebP), 1);
+        assert_eq!(count_frames(&webp, ImageFormat::WebP), 3);
+
+        // 超大的块长度在这个块之后停止
+        let mut hostile = b"RIFF\0\0\0\0WEBP".to_vec();
+        hostile.extend(b"ANMF");
+        hostile.extend(u32::MAX.to_le_bytes());
+        hostile.extend(webp_chunk(b"ANMF", &[0; 17]));
+        assert_eq!(count_frames(&hostile, ImageFormat::WebP), 1);
+        assert_eq!(count_frames(&webp[..webp.len() - 9], ImageFormat::WebP), 3);
+    }
+
+    // 默认限制下手机照片与长截图都能处理
+    #[test]
+    fn default_limits_accept_phone_photos() {
+        let limits = DecodeLimits::from_config(None);
+        for (width, height) in [(4032, 3024), (3024, 4032), (1080, 12000)] {
+            assert!(
+                limits.check_working_set(width, height).is_ok(),
+                "{width}x{height}"
+            );
+        }
+        assert!(limits.check_working_set(20000, 20000).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use rayon::prelude::*;
+
+use super::convolution::{box_kernel, convolve_ndarray, gaussian_kernel_ndarray};
+use crate::error::OLIError;
+
+// 去噪算法
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DenoiseMode {
+    Gaussian,
+    Median,
+    Bilateral,
+    Guided,
+    NlMeans,
+}
+
+impl DenoiseMode {
+    pub fn from_name(name: &str) -> Result<Self, OLIError> {
+        match name {
+            "gaussian" => Ok(DenoiseMode::Gaussian),
+            "median" => Ok(DenoiseMode::Median),
+            "bilateral" => Ok(DenoiseMode::Bilateral),
+            "guided" => Ok(DenoiseMode::Guided),
+            "nlmeans" => Ok(DenoiseMode::NlMeans),
+            _ => Err(OLIError::InvalidConfig(format!(
+                "unknown denoise mode \"{name}\""
+            ))),
+        }
+    }
+}
+
+// 去噪，strength 为强度（1 为默认，0 时原样返回），scale 为超采样倍率
+pub fn denoise(
+    pixels: &[f32],
+    width: u32,
+    height: u32,
+    mode: DenoiseMode,
+    strength: f32,
+    scale: u32,
+) -> Vec<f32> {
+    // 强度为 0 时所有算法都不做处理（导向滤波的 eps 为 0 时平坦区域会得到 0/0）
+    if strength <= 0.0 {
+        return pixels.to_vec();
+    }
+    let scale = scale as f32;
+    match mode {
+        DenoiseMode::Gaussian => {
+            let radius = (strength * scale).ceil() as usize;
+            gaussian(pixels, width, height, radius, 0.8 * strength * scale)
+        }
+        DenoiseMode::Median => {
+            // 至少使用 3×3 窗口
+            let radius = (strength * scale).round().max(1.0) as usize;
+            median(pixels, width, height, radius)
+        }
+        DenoiseMode::Bilateral => bilateral(pixels, width, height, 1.5 * scale, 20.0 * strength),
+        DenoiseMode::Guided => {
+            let radius = 2.0 * scale;
+            let eps = (10.0 * strength).powi(2);
+            guided(pixels, width, height, radius, eps)
+        }
+        DenoiseMode::NlMeans => nl_means(
+            pixels,
+            width,
+            height,
+            scale as usize,
+            3 * scale as usize,
+            10.0 * strength,
+        ),
+    }
+}
+
+// 高斯滤波 - 默认的去噪方式，默认强度下为 σ=0.8 的 3×3 核
+fn gaussian(pixels: &[f32], width: u32, height: u32, radius: usize, sigma: f32) -> Vec<f32> {
+    if radius == 0 || sigma <= 0.0 {
+        return pixels.to_vec();
+    }
+    let kernel = gaussian_kernel_ndarray(2 * radius + 1, sigma);
+    convolve_ndarray(pixels, width, height, &kernel)
+}
+
+// 中值滤波 - 去除椒盐噪声，保留边缘
+fn median(pixels: &[f32], width: u32, height: u32, radius: usize) -> Vec<f32> {
+    let w = width as usize;
+    let h = height as usize;
+    let r = radius as i32;
+
+    let mut output = vec![0.0f32; w * h];
+    output.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        let mut window = Vec::with_capacity((2 * radius + 1) * (2 * radius + 1));
+        for (x, out) in row.iter_mut().enumerate() {
+            window.clear();
+            for dy in -r..=r {
+                let py = (y as i32 + dy).clamp(0, h as i32 - 1) as usize;
+                for dx in -r..=r {
+                    let px = (x as i32 + dx).clamp(0, w as i32 - 1) as usize;
+                    window.push(pixels[py * w + px]);
+                }
+            }
+            let mid = window.len() / 2;
+            let (_, median, _) = window.select_nth_unstable_by(mid, f32::total_cmp);
+            *out = *median;
+        }
+    });
+
+    output
+}
+
+// 双边滤波 - 按空间距离与亮度差共同加权，亮度差超过 sigma_range 的邻域几乎不参与平均
+pub fn bilateral(
+    pixels: &[f32],
+    width: u32,
+    height: u32,
+    sigma_space: f32,
+    sigma_range: f32,
+) -> Vec<f32> {
+    if sigma_range <= 0.0 {
+        return pixels.to_vec();
+    }
+
+    let w = width as usize;
+    let h = height as usize;
+    let r = (2.0 * sigma_space).ceil() as i32;
+
+    let mut spatial = Vec::new();
+    for dy in -r..=r {
+        for dx in -r..=r {
+            let d2 = (dx * dx + dy * dy) as f32;
+            spatial.push((dx, dy, (-d2 / (2.0 * sigma_space * sigma_space)).exp()));
+        }
+    }
+    let range_coeff = -1.0 / (2.0 * sigma_range * sigma_range);
+
+    let mut output = vec![0.0f32; w * h];
+    output.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        for (x, out) in row.iter_mut().enumerate() {
+            let center = pixels[y * w + x];
+            let mut sum = 0.0;
+            let mut weight_sum = 0.0;
+            for &(dx, dy, ws) in &spatial {
+                let px = (x as i32 + dx).clamp(0, w as i32 - 1) as usize;
+                let py = (y as i32 + dy).clamp(0, h as i32 - 1) as usize;
+                let v = pixels[py * w + px];
+                let diff = v - center;
+                let weight = ws * (diff * diff * range_coeff).exp();
+                sum += v * weight;
+                weight_sum += weight;
+            }
+            *out = sum / weight_sum;
+        }
+    });
+
+    output
+}
+
+// 引导滤波（He et al.），以自身为引导图：方差远大于 eps 的区域（边缘）保留，平坦区域取局部均值
+fn guided(pixels: &[f32], width: u32, height: u32, radius: f32, eps: f32) -> Vec<f32> {
+    let kernel = box_kernel(radius);
+    let mean = |values: &[f32]| convolve_ndarray(values, width, height, &kernel);
+
+    let squared: Vec<f32> = pixels.iter().map(|v| v * v).collect();
+    let mean_i = mean(pixels);
+    let mean_ii = mean(&squared);
+
+    let mut a = vec![0.0f32; pixels.len()];
+    let mut b = vec![0.0f32; pixels.len()];
+    for i in 0..pixels.len() {
+        let var = (mean_ii[i] - mean_i[i] * mean_i[i]).max(0.0);
+        a[i] = var / (var + eps);
+        b[i] = mean_i[i] - a[i] * mean_i[i];
+    }
+
+    let mean_a = mean(&a);
+    let mean_b = mean(&b);
+    pixels
+        .iter()
+        .zip(mean_a.iter().zip(&mean_b))
+        .map(|(v, (a, b))| a * v + b)
+        .collect()
+}
+
+// 非局部均值 - 在搜索窗口内按图块相似度加权平均，h 越大平滑越强
+fn nl_means(
+    pixels: &[f32],
+    width: u32,
+    height: u32,
+    patch_radius: usize,
+    search_radius: usize,
+    h: f32,
+) -> Vec<f32> {
+    if h <= 0.0 {
+        return pixels.to_vec();
+    }
+
+    let w = width as usize;
+    let ht = height as usize;
+    let pr = patch_radius as i32;
+    let sr = search_radius as i32;
+    let patch_area = ((2 * pr + 1) * (2 * pr + 1)) as f32;
+    let inv_h2 = 1.0 / (h * h);
+
+    let at = |x: i32, y: i32| -> f32 {
+        let px = x.clamp(0, w as i32 - 1) as usize;
+        let py = y.clamp(0, ht as i32 - 1) as usize;
+        pixels[py * w + px]
+    };
+
+    let mut output = vec![0.0f32; w * ht];
+    output.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        let y = y as i32;
+        for (x, out) in row.iter_mut().enumerate() {
+            let x = x as i32;
+            let mut sum = 0.0;
+            let mut weight_sum = 0.0;
+            for sy in -sr..=sr {
+                for sx in -sr..=sr {
+                    let mut dist = 0.0;
+                    for py in -pr..=pr {
+                        for px in -pr..=pr {
+                            let d = at(x + px, y + py) - at(x + sx + px, y + sy + py);
+                            dist += d * d;
+                        }
+                    }
+                    let weight = (-(dist / patch_area) * inv_h2).exp();
+                    sum += at(x + sx, y + sy) * weight;
+                    weight_sum += weight;
+                }
+            }
+            *out = sum / weight_sum;
+        }
+    });
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_strength_leaves_pixels_unchanged() {
+        // 平坦区域与噪声各占一半，导向滤波在平坦区域曾得到 NaN
+        let (width, height) = (32, 24);
+        let mut state = 0x2545_f491_u32;
+        let pixels: Vec<f32> = (0..width * height)
+            .map(|i| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                if i % width < width / 2 {
+                    200.0
+                } else {
+                    (state >> 24) as f32
+                }
+            })
+            .collect();
+
+        for mode in [
+            DenoiseMode::Gaussian,
+            DenoiseMode::Median,
+            DenoiseMode::Bilateral,
+            DenoiseMode::Guided,
+            DenoiseMode::NlMeans,
+        ] {
+            for scale in [1, 2] {
+                assert_eq!(
+                    denoise(&pixels, width, height, mode, 0.0, scale),
+                    pixels,
+                    "{mode:?} at scale {scale}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::VecDeque;
+
+use super::convolution::{convolve_ndarray, gaussian_kernel_ndarray, sobel_gradients};
+use crate::error::OLIError;
+
+// 线稿提取算法
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LineExtractor {
+    // 128 + 原图 - 模糊图（原有的高通滤波）
+    HighPass,
+    // 高斯差分
+    Dog,
+    // 扩展高斯差分，软阈值
+    Xdog,
+    // Canny边缘检测，双阈值滞后连接
+    Canny,
+}
+
+impl LineExtractor {
+    pub fn from_name(name: &str) -> Result<Self, OLIError> {
+        match name {
+            "highpass" => Ok(LineExtractor::HighPass),
+            "dog" => Ok(LineExtractor::Dog),
+            "xdog" => Ok(LineExtractor::Xdog),
+            "canny" => Ok(LineExtractor::Canny),
+            _ => Err(OLIError::InvalidConfig(format!(
+                "unknown line extractor \"{name}\""
+            ))),
+        }
+    }
+
+    // 结果以128为中心，需要经过 lightCut/darkCut 拉伸
+    pub fn needs_cut(self) -> bool {
+        matches!(self, LineExtractor::HighPass | LineExtractor::Dog)
+    }
+}
+
+// 高斯模糊，核覆盖 ±3σ
+fn gaussian_blur(pixels: &[f32], width: u32, height: u32, sigma: f32) -> Vec<f32> {
+    let half = (sigma * 3.0).ceil().max(1.0) as usize;
+    let kernel = gaussian_kernel_ndarray(2 * half + 1, sigma);
+    convolve_ndarray(pixels, width, height, &kernel)
+}
+
+// 高斯差分：128 + G(σ) - G(kσ)，与高通滤波的输出范围一致
+pub fn dog(pixels: &[f32], width: u32, height: u32, sigma: f32, k: f32) -> Vec<f32> {
+    let narrow = gaussian_blur(pixels, width, height, sigma);
+    let wide = gaussian_blur(pixels, width, height, sigma * k);
+    narrow
+        .iter()
+        .zip(&wide)
+        .map(|(n, w)| 128.0 + n - w)
+        .collect()
+}
+
+// XDoG参数
+#[derive(Clone, Copy)]
+pub struct XdogParams {
+    pub sigma: f32,
+    pub k: f32,
+    // 锐化强度
+    pub p: f32,
+    // 软阈值（0-255 亮度）
+    pub epsilon: f32,
+    // 阈值以下过渡到黑色的陡峭程度
+    pub phi: f32,
+}
+
+// XDoG（Winnemöller 2012）：S = (1+p)·G(σ) - p·G(kσ)，
+// S ≥ ε 时为白色，否则按 1 + tanh(φ·(S-ε)) 平滑过渡到黑色，亮度在 0-1 范围内计算
+pub fn xdog(pixels: &[f32], width: u32, height: u32, params: &XdogParams) -> Vec<f32> {
+    let XdogParams {
+        sigma,
+        k,
+        p,
+        epsilon,
+        phi,
+    } = *params;
+    let narrow = gaussian_blur(pixels, width, height, sigma);
+    let wide = gaussian_blur(pixels, width, height, sigma * k);
+    let epsilon = epsilon / 255.0;
+    narrow
+        .iter()
+        .zip(&wide)
+        .map(|(n, w)| {
+            let s = ((1.0 + p) * n - p * w) / 255.0;
+            if s >= epsilon {
+                255.0
+            } else {
+                255.0 * (1.0 + (phi * (s - epsilon)).tanh())
+            }
+        })
+        .collect()
+}
+
+// Canny边缘检测：高斯平滑、Sobel梯度、非极大值抑制、双阈值滞后连接
+// 阈值为Sobel梯度幅值，返回白底黑线
+pub fn canny(pixels: &[f32], width: u32, height: u32, sigma: f32, low: f32, high: f32) -> Vec<f32> {
+    let w = width as usize;
+    let h = height as usize;
+
+    let smoothed = gaussian_blur(pixels, width, height, sigma);
+    let (gx, gy) = sobel_gradients(&smoothed, width, height);
+    let magnitude: Vec<f32> = gx.iter().zip(&gy).map(|(x, y)| x.hypot(*y)).collect();
+
+    // 非极大值抑制：梯度方向量化为 0°/45°/90°/135°，只保留方向上的局部最大值
+    let mut thin = vec![0.0f32; w * h];
+    for y in 1..h.saturating_sub(1) {
+        for x in 1..w.saturating_sub(1) {
+            let i = y * w + x;
+            let m = magnitude[i];
+            if m < low {
+                continue;
+            }
+
+            let angle = gy[i].atan2(gx[i]).to_degrees().rem_euclid(180.0);
+            let (a, b) = if !(22.5..157.5).contains(&angle) {
+                (i - 1, i + 1)
+            } else if angle < 67.5 {
+                (i - w - 1, i + w + 1)
+            } else if angle < 112.5 {
+                (i - w, i + w)
+            } else {
+                (i - w + 1, i + w - 1)
+            };
+
+            if m >= magnitude[a] && m >= magnitude[b] {
+                thin[i] = m;
+            }
+        }
+    }
+
+    // 滞后连接：从强边缘出发，沿8邻域连接弱边缘
+    let mut edges = vec![false; w * h];
+    let mut queue: VecDeque<usize> = VecDeque::new();
+    for (i, &m) in thin.iter().enumerate() {
+        if m >= high {
+            edges[i] = true;
+            queue.push_back(i);
+        }
+    }
+
+    while let Some(i) = queue.pop_front() {
+        let x = (i % w) as i32;
+        let y = (i / w) as i32;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let nx = x + dx;
+                let ny = y + dy;
+                if nx < 0 || ny < 0 || nx >= w as i32 || ny >= h as i32 {
+                    continue;
+                }
+                let j = ny as usize * w + nx as usize;
+                if !edges[j] && thin[j] >= low {
+                    edges[j] = true;
+                    queue.push_back(j);
+                }
+            }
+        }
+    }
+
+    edges.iter().map(|&e| if e { 0.0 } else { 255.0 }).collect()
+}
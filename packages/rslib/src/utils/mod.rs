@@ -1 +1,17 @@
+pub mod antialias;
+pub mod contrast;
+pub mod convolution;
+pub mod curves;
+pub mod deblock;
+pub mod debug;
+pub mod decode;
+pub mod denoise;
 pub mod image_processing;
+pub mod line_extraction;
+pub mod morphology;
+pub mod pipeline;
+pub mod provenance;
+pub mod stages;
+pub mod template;
+pub mod text;
+pub mod watermark;
@@ -0,0 +1,91 @@
+use rayon::prelude::*;
+
+use crate::error::OLIError;
+
+// 形态学结构元素形状
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StructuringElement {
+    Disk,
+    Square,
+    Diamond,
+}
+
+impl StructuringElement {
+    pub fn from_name(name: &str) -> Result<Self, OLIError> {
+        match name {
+            "disk" => Ok(StructuringElement::Disk),
+            "square" => Ok(StructuringElement::Square),
+            "diamond" => Ok(StructuringElement::Diamond),
+            _ => Err(OLIError::InvalidConfig(format!(
+                "unknown line shape \"{name}\""
+            ))),
+        }
+    }
+
+    // 半径为 radius（可为小数）的结构元素包含的偏移量
+    fn offsets(self, radius: f32) -> Vec<(i32, i32)> {
+        let reach = radius.floor() as i32;
+        let mut offsets = Vec::new();
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let inside = match self {
+                    StructuringElement::Disk => (dx * dx + dy * dy) as f32 <= radius * radius,
+                    StructuringElement::Square => true,
+                    StructuringElement::Diamond => (dx.abs() + dy.abs()) as f32 <= radius,
+                };
+                if inside {
+                    offsets.push((dx, dy));
+                }
+            }
+        }
+        offsets
+    }
+}
+
+// 灰度形态学：在结构元素覆盖的邻域内取最小值（腐蚀）或最大值（膨胀）
+fn morphology(
+    pixels: &[f32],
+    width: u32,
+    height: u32,
+    offsets: &[(i32, i32)],
+    pick: fn(f32, f32) -> f32,
+) -> Vec<f32> {
+    let w = width as usize;
+    let h = height as usize;
+
+    let mut output = vec![0.0f32; w * h];
+    output.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        for (x, out) in row.iter_mut().enumerate() {
+            let mut value = pixels[y * w + x];
+            for &(dx, dy) in offsets {
+                let px = (x as i32 + dx).clamp(0, w as i32 - 1) as usize;
+                let py = (y as i32 + dy).clamp(0, h as i32 - 1) as usize;
+                value = pick(value, pixels[py * w + px]);
+            }
+            *out = value;
+        }
+    });
+
+    output
+}
+
+// 调整线条粗细：线条为暗色，line_width > 0 时腐蚀（取最小值）加粗，< 0 时膨胀（取最大值）变细
+// |line_width| 为结构元素半径（像素）
+pub fn adjust_line_width(
+    pixels: &[f32],
+    width: u32,
+    height: u32,
+    line_width: f32,
+    shape: StructuringElement,
+) -> Vec<f32> {
+    let offsets = shape.offsets(line_width.abs());
+    if offsets.len() <= 1 {
+        return pixels.to_vec();
+    }
+
+    if line_width > 0.0 {
+        morphology(pixels, width, height, &offsets, f32::min)
+    } else {
+        morphology(pixels, width, height, &offsets, f32::max)
+    }
+}
@@ -0,0 +1,939 @@
+use std::borrow::Cow;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+#[cfg(feature = "toml-pipeline")]
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use super::antialias::AntialiasMode;
+use super::contrast::ContrastMode;
+use super::convolution::KernelShape;
+use super::curves::Curve;
+use super::deblock::{JpegQuality, estimate_jpeg_quality};
+use super::debug::thumbnail;
+use super::decode::{DecodeLimits, load_image};
+use super::denoise::DenoiseMode;
+use super::image_processing::{RenderInfo, encode_png};
+use super::line_extraction::{LineExtractor, XdogParams};
+use super::morphology::StructuringElement;
+use super::stages::{
+    Antialias, Colorize, Composite, Contrast, Cut, CutMode, Deblock, Denoise, Duotone, Grayscale,
+    Light, LineExtraction, LineWidth, ProvenanceMark, Resize, Sharpen, Template, ToneCurve,
+    Watermark, WatermarkSource,
+};
+use super::template::parse_color;
+use super::watermark::{TileLayout, WatermarkLayout, WatermarkMode};
+use crate::error::OLIError;
+use crate::{OLIConfig, PipelineDefinition};
+
+// 在各阶段之间传递的处理状态
+pub struct Frame {
+    pub limits: DecodeLimits,
+    // 解码得到的原图（去块在这里进行）与从JPEG量化表估算的压缩质量
+    pub source: DynamicImage,
+    pub jpeg_quality: Option<JpegQuality>,
+    // 缩放到处理尺寸的原图
+    pub resized: RgbaImage,
+    // 当前的灰度/线稿缓冲区（0-255 浮点）与尺寸
+    pub pixels: Vec<f32>,
+    pub width: u32,
+    pub height: u32,
+    // 缓冲区相对输出尺寸的超采样倍率
+    pub scale: u32,
+    // 线稿是否以128为中心，需要按 light_cut/dark_cut 拉伸
+    pub needs_cut: bool,
+    pub light_cut: Option<f32>,
+    pub dark_cut: Option<f32>,
+    // 着色时暗于128的像素百分比
+    pub ink_coverage: f32,
+    // 着色后的输出图片，之后的阶段在它上面绘制
+    pub image: RgbaImage,
+    // 调试模式下各阶段额外记录的中间结果（例如抗锯齿的边缘图与混合权重），不调试时为 None
+    pub debug: Option<Vec<(String, RgbaImage)>>,
+}
+
+impl Frame {
+    pub fn new(
+        source: DynamicImage,
+        jpeg_quality: Option<JpegQuality>,
+        limits: DecodeLimits,
+    ) -> Self {
+        Frame {
+            limits,
+            source,
+            jpeg_quality,
+            resized: RgbaImage::new(0, 0),
+            pixels: Vec::new(),
+            width: 0,
+            height: 0,
+            scale: 1,
+            needs_cut: false,
+            light_cut: None,
+            dark_cut: None,
+            ink_coverage: 0.0,
+            image: RgbaImage::new(0, 0),
+            debug: None,
+        }
+    }
+
+    // 当前缓冲区对应的灰度图
+    pub fn gray_image(&self) -> RgbaImage {
+        gray_image(&self.pixels, self.width, self.height)
+    }
+
+    // 输出图片：还没有着色时使用当前缓冲区，还没有灰度缓冲区时使用原图
+    pub fn output(&self) -> RgbaImage {
+        if self.image.width() > 0 {
+            self.image.clone()
+        } else if !self.pixels.is_empty() {
+            self.gray_image()
+        } else {
+            self.source.to_rgba8()
+        }
+    }
+
+    // 还没有着色时把输出图片设为 output()，供在图片上绘制的阶段使用
+    pub fn materialize(&mut self) {
+        if self.image.width() == 0 {
+            self.image = self.output();
+        }
+    }
+
+    // 当前状态的快照：着色后为输出图片，之前依次为灰度缓冲区、缩放后的原图、原图
+    pub fn snapshot(&self) -> RgbaImage {
+        if self.image.width() > 0 {
+            self.image.clone()
+        } else if !self.pixels.is_empty() {
+            self.gray_image()
+        } else if self.resized.width() > 0 {
+            self.resized.clone()
+        } else {
+            self.source.to_rgba8()
+        }
+    }
+
+    // 调试模式下记录名为 name 的中间结果（缩小为拼图缩略图），不调试时不会调用 image
+    pub fn record(&mut self, name: &str, image: impl FnOnce() -> RgbaImage) {
+        if let Some(records) = &mut self.debug {
+            records.push((name.to_string(), thumbnail(&image())));
+        }
+    }
+
+    pub fn info(&self) -> RenderInfo {
+        RenderInfo {
+            light_cut: self.light_cut,
+            dark_cut: self.dark_cut,
+            ink_coverage: self.ink_coverage,
+        }
+    }
+}
+
+// 把 width×height 的浮点缓冲区量化为灰度图
+pub fn gray_image(pixels: &[f32], width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        let v = pixels[(y * width + x) as usize].round().clamp(0.0, 255.0) as u8;
+        Rgba([v, v, v, 255])
+    })
+}
+
+// 处理流程中的一个阶段
+pub trait Stage {
+    // 阶段名称，用于在流水线中查找、替换或移除
+    fn name(&self) -> &str;
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError>;
+}
+
+// 按顺序执行的一组阶段
+pub struct Pipeline {
+    limits: DecodeLimits,
+    stages: Vec<Box<dyn Stage>>,
+}
+
+impl Pipeline {
+    pub fn new(limits: DecodeLimits) -> Self {
+        Pipeline {
+            limits,
+            stages: Vec::new(),
+        }
+    }
+
+    // 在末尾添加一个阶段
+    pub fn stage(mut self, stage: impl Stage + 'static) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    // 在 index 处插入一个阶段（超出范围时添加到末尾）
+    pub fn insert(mut self, index: usize, stage: impl Stage + 'static) -> Self {
+        let index = index.min(self.stages.len());
+        self.stages.insert(index, Box::new(stage));
+        self
+    }
+
+    // 移除所有名为 name 的阶段
+    pub fn remove(mut self, name: &str) -> Self {
+        self.stages.retain(|stage| stage.name() != name);
+        self
+    }
+
+    // 把第一个名为 name 的阶段替换为 stage，没有时不做处理
+    pub fn replace(mut self, name: &str, stage: impl Stage + 'static) -> Self {
+        if let Some(index) = self.position(name) {
+            self.stages[index] = Box::new(stage);
+        }
+        self
+    }
+
+    // 第一个名为 name 的阶段的位置
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.stages.iter().position(|stage| stage.name() == name)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.stages.iter().map(|stage| stage.name()).collect()
+    }
+
+    // 解码图片并依次执行各阶段，返回PNG与实际使用的参数
+    pub fn run(&self, input: &[u8]) -> Result<(Vec<u8>, RenderInfo), OLIError> {
+        let frame = self.run_frame(input, |_, _| {})?;
+        Ok((encode_png(&frame.output())?, frame.info()))
+    }
+
+    // 与 run 相同，每个阶段执行完后调用 inspect，返回最终的处理状态
+    pub fn run_frame(
+        &self,
+        input: &[u8],
+        mut inspect: impl FnMut(&str, &Frame),
+    ) -> Result<Frame, OLIError> {
+        self.execute(input, false, |name, frame| inspect(name, frame))
+    }
+
+    // 调试模式：依次返回每个阶段执行后的快照（缩小为拼图缩略图），阶段额外记录的中间结果排在该阶段之前
+    pub fn run_debug(&self, input: &[u8]) -> Result<Vec<(String, RgbaImage)>, OLIError> {
+        let mut snapshots = Vec::new();
+        self.execute(input, true, |name, frame| {
+            snapshots.extend(frame.debug.as_mut().map(std::mem::take).unwrap_or_default());
+            snapshots.push((name.to_string(), thumbnail(&frame.snapshot())));
+        })?;
+        Ok(snapshots)
+    }
+
+    fn execute(
+        &self,
+        input: &[u8],
+        debug: bool,
+        mut inspect: impl FnMut(&str, &mut Frame),
+    ) -> Result<Frame, OLIError> {
+        let source = load_image(input, &self.limits)?;
+        let mut frame = Frame::new(source, estimate_jpeg_quality(input), self.limits.clone());
+        if debug {
+            frame.debug = Some(Vec::new());
+        }
+        for stage in &self.stages {
+            stage.apply(&mut frame)?;
+            inspect(stage.name(), &mut frame);
+        }
+        Ok(frame)
+    }
+
+    // 按配置构建流水线：设置了 pipeline 时依次构建其中的阶段，否则构建默认流程
+    pub fn from_config(config: Option<&OLIConfig>) -> Result<Self, OLIError> {
+        let mut pipeline = Pipeline::new(DecodeLimits::from_config(config));
+
+        if let Some(config) = config
+            && let Some(definition) = &config.pipeline
+        {
+            // 超采样倍率由缩放阶段决定，读取的是顶层的 antialias，阶段中不能改变倍率
+            let supersample = antialias_scale(config)?;
+            for (name, stage_config) in resolve_stages(config, definition)? {
+                if antialias_scale(&stage_config)? != supersample {
+                    return Err(OLIError::InvalidConfig(format!(
+                        "pipeline stage \"{name}\" cannot change the supersampling of antialias, set \"antialias\" at the top level"
+                    )));
+                }
+                pipeline
+                    .stages
+                    .extend(config_stage(&name, Some(&stage_config), true)?);
+            }
+            return Ok(pipeline);
+        }
+
+        for name in DEFAULT_STAGES {
+            pipeline.stages.extend(config_stage(name, config, false)?);
+        }
+        Ok(pipeline)
+    }
+}
+
+// 配置中的抗锯齿算法需要的超采样倍率
+fn antialias_scale(config: &OLIConfig) -> Result<u32, OLIError> {
+    Ok(AntialiasMode::from_name(config.antialias.as_deref().unwrap_or("blur"))?.scale())
+}
+
+// 默认流程的阶段顺序
+pub const DEFAULT_STAGES: [&str; 18] = [
+    "deblock",
+    "resize",
+    "gray",
+    "light",
+    "curve",
+    "denoise",
+    "contrast",
+    "lines",
+    "cut",
+    "line_width",
+    "antialias",
+    "sharpen",
+    "output_curve",
+    "colorize",
+    "composite",
+    "watermark",
+    "template",
+    "mark",
+];
+
+// 局部直方图均衡每个方向上的分块数上限，每块保存一张 256 项的映射表
+const MAX_CLAHE_TILES: u32 = 64;
+
+// 去噪强度上限，高斯与中值滤波的窗口半径与强度成正比
+const MAX_DENOISE_STRENGTH: f32 = 10.0;
+
+// 线条粗细调整的结构元素半径上限（像素），每个像素的开销与半径的平方成正比
+const MAX_LINE_WIDTH: f32 = 20.0;
+
+// 锐化半径（高斯模糊 sigma）的上限，卷积核覆盖 ±2σ
+const MAX_SHARPEN_RADIUS: f32 = 32.0;
+
+// 默认流程中跳过不起作用的阶段；自定义流水线中明确列出的阶段缺少参数时报错，不能静默丢弃
+fn skip_stage(name: &str, param: &str, explicit: bool) -> Result<Option<Box<dyn Stage>>, OLIError> {
+    if explicit {
+        return Err(OLIError::InvalidConfig(format!(
+            "pipeline stage \"{name}\" has no effect without \"{param}\""
+        )));
+    }
+    Ok(None)
+}
+
+// 按配置构建名为 name 的阶段，对应的开关关闭或参数不起作用时返回 None
+// explicit 为 true 时（自定义流水线中列出的阶段）忽略开关，参数不起作用时报错
+fn config_stage(
+    name: &str,
+    config: Option<&OLIConfig>,
+    explicit: bool,
+) -> Result<Option<Box<dyn Stage>>, OLIError> {
+    let kiss = config.and_then(|c| c.kiss).unwrap_or(true);
+    let antialias_mode = AntialiasMode::from_name(
+        config
+            .and_then(|c| c.antialias.as_deref())
+            .unwrap_or("blur"),
+    )?;
+
+    let stage: Box<dyn Stage> = match name {
+        // 0. JPEG去块 - 高通滤波会把8×8块边界放大成网格，低质量JPEG在原始分辨率上先做平滑
+        "deblock" => Box::new(Deblock {
+            enabled: config.and_then(|c| c.deblock),
+            quality: config.and_then(|c| c.deblock_quality).unwrap_or(75),
+        }),
+
+        // 缩放 - 超采样抗锯齿时以放大后的尺寸提取线稿，抗锯齿阶段再缩小回来
+        "resize" => Box::new(Resize {
+            zoom: config.and_then(|c| c.zoom).unwrap_or(1.0),
+            supersample: antialias_mode.scale(),
+        }),
+
+        // 1. 转灰度
+        "gray" => Box::new(Grayscale),
+
+        // 2. light调整
+        "light" => {
+            let light = config.and_then(|c| c.light).unwrap_or(0.0);
+            if light == 0.0 {
+                return skip_stage(name, "light", explicit);
+            }
+            Box::new(Light { amount: light })
+        }
+
+        // 2.1 色调曲线
+        "curve" => match config.and_then(|c| c.curve.as_ref()) {
+            Some(curve) => Box::new(ToneCurve::input(Curve::from_config(curve)?)),
+            None => return skip_stage(name, "curve", explicit),
+        },
+
+        // 3. 去噪
+        "denoise" => {
+            let mode = DenoiseMode::from_name(
+                config
+                    .and_then(|c| c.denoise_mode.as_deref())
+                    .unwrap_or("gaussian"),
+            )?;
+            if !explicit && !config.and_then(|c| c.denoise).unwrap_or(true) {
+                return Ok(None);
+            }
+            let strength = config.and_then(|c| c.denoise_strength).unwrap_or(1.0);
+            if !(0.0..=MAX_DENOISE_STRENGTH).contains(&strength) {
+                return Err(OLIError::InvalidConfig(format!(
+                    "denoise_strength must be between 0 and {MAX_DENOISE_STRENGTH}, got {strength}"
+                )));
+            }
+            Box::new(Denoise { mode, strength })
+        }
+
+        // 3.1 局部对比度增强
+        "contrast" => {
+            let mode = ContrastMode::from_name(
+                config.and_then(|c| c.contrast.as_deref()).unwrap_or("none"),
+            )?;
+            if mode == ContrastMode::None {
+                return skip_stage(name, "contrast", explicit);
+            }
+            let tiles = config.and_then(|c| c.clahe_tiles).unwrap_or(8);
+            if tiles > MAX_CLAHE_TILES {
+                return Err(OLIError::InvalidConfig(format!(
+                    "clahe_tiles must be at most {MAX_CLAHE_TILES}, got {tiles}"
+                )));
+            }
+            Box::new(Contrast {
+                mode,
+                tiles,
+                clip: config.and_then(|c| c.clahe_clip).unwrap_or(2.0),
+            })
+        }
+
+        // 4. 提取线稿
+        "lines" => {
+            let kernel_shape = KernelShape::from_name(
+                config
+                    .and_then(|c| c.kernel_shape.as_deref())
+                    .unwrap_or("box"),
+            )?;
+            let extractor = LineExtractor::from_name(
+                config
+                    .and_then(|c| c.line_extractor.as_deref())
+                    .unwrap_or("highpass"),
+            )?;
+            Box::new(LineExtraction {
+                extractor,
+                kernel: kernel_radius(config)?.map(|radius| (kernel_shape, radius)),
+                params: XdogParams {
+                    sigma: config.and_then(|c| c.line_sigma).unwrap_or(1.0).max(0.1),
+                    k: config.and_then(|c| c.dog_k).unwrap_or(1.6).max(1.0),
+                    p: config.and_then(|c| c.xdog_p).unwrap_or(20.0),
+                    epsilon: config.and_then(|c| c.xdog_epsilon).unwrap_or(80.0),
+                    phi: config.and_then(|c| c.xdog_phi).unwrap_or(10.0),
+                },
+                canny_low: config.and_then(|c| c.canny_low).unwrap_or(40.0),
+                canny_high: config.and_then(|c| c.canny_high).unwrap_or(100.0),
+            })
+        }
+
+        // 5. lightCut/darkCut
+        "cut" => {
+            let mode = if config.and_then(|c| c.auto_cut).unwrap_or(false) {
+                CutMode::AutoCut(config.and_then(|c| c.ink_coverage).unwrap_or(10.0))
+            } else if config.and_then(|c| c.auto_levels).unwrap_or(false) {
+                CutMode::AutoLevels
+            } else {
+                CutMode::Fixed
+            };
+            Box::new(Cut {
+                light_cut: config.and_then(|c| c.light_cut).unwrap_or(128) as f32,
+                dark_cut: config.and_then(|c| c.dark_cut).unwrap_or(118) as f32,
+                mode,
+            })
+        }
+
+        // 5.1 线条粗细
+        "line_width" => {
+            let shape = StructuringElement::from_name(
+                config
+                    .and_then(|c| c.line_shape.as_deref())
+                    .unwrap_or("disk"),
+            )?;
+            let width = config.and_then(|c| c.line_width).unwrap_or(0.0);
+            if width.abs() > MAX_LINE_WIDTH {
+                return Err(OLIError::InvalidConfig(format!(
+                    "line_width must be between -{MAX_LINE_WIDTH} and {MAX_LINE_WIDTH}, got {width}"
+                )));
+            }
+            if width == 0.0 {
+                return skip_stage(name, "line_width", explicit);
+            }
+            Box::new(LineWidth { width, shape })
+        }
+
+        // 6. 抗锯齿
+        "antialias" => Box::new(Antialias {
+            mode: antialias_mode,
+            threshold: config
+                .and_then(|c| c.antialias_threshold)
+                .unwrap_or(antialias_mode.default_threshold()),
+        }),
+
+        // 7. 适度锐化
+        "sharpen" => {
+            if !explicit && !config.and_then(|c| c.sharpen).unwrap_or(true) {
+                return Ok(None);
+            }
+            let radius = config
+                .and_then(|c| c.sharpen_radius)
+                .unwrap_or(0.9)
+                .max(0.1);
+            if radius > MAX_SHARPEN_RADIUS {
+                return Err(OLIError::InvalidConfig(format!(
+                    "sharpen_radius must be at most {MAX_SHARPEN_RADIUS}, got {radius}"
+                )));
+            }
+            Box::new(Sharpen {
+                amount: config.and_then(|c| c.sharpen_amount).unwrap_or(1.0),
+                radius,
+                threshold: config.and_then(|c| c.sharpen_threshold).unwrap_or(0.0),
+            })
+        }
+
+        // 7.1 输出色调曲线
+        "output_curve" => match config.and_then(|c| c.output_curve.as_ref()) {
+            Some(curve) => Box::new(ToneCurve::output(Curve::from_config(curve)?)),
+            None => return skip_stage(name, "output_curve", explicit),
+        },
+
+        // 8. 着色
+        "colorize" => Box::new(Colorize { kiss }),
+
+        // 双色调着色，不在默认流程中
+        "duotone" => Box::new(Duotone {
+            dark: parse_color(
+                config
+                    .and_then(|c| c.duotone_dark.as_deref())
+                    .unwrap_or("#000000"),
+            )?,
+            light: parse_color(
+                config
+                    .and_then(|c| c.duotone_light.as_deref())
+                    .unwrap_or("#ffffff"),
+            )?,
+        }),
+
+        // 9. 白色背景合成
+        "composite" => Box::new(Composite),
+
+        // 10. 水印
+        "watermark" => {
+            let layout = WatermarkLayout::from_config(config)?;
+            let mode = WatermarkMode::from_name(
+                config
+                    .and_then(|c| c.watermark_mode.as_deref())
+                    .unwrap_or("single"),
+            )?;
+            if !explicit && !config.and_then(|c| c.watermark).unwrap_or(true) {
+                return Ok(None);
+            }
+            let text = config
+                .and_then(|c| c.watermark_text.clone())
+                .filter(|text| !text.trim().is_empty());
+            let source = match text {
+                Some(text) => WatermarkSource::Text {
+                    text,
+                    font: config.and_then(|c| c.watermark_font.clone()),
+                },
+                None => WatermarkSource::Image {
+                    image: config.and_then(|c| c.watermark_image.clone()),
+                    rows: config.and_then(|c| c.watermark_rows),
+                    cols: config.and_then(|c| c.watermark_cols),
+                    sprite: config.and_then(|c| c.watermark_sprite),
+                    hajimei: config.and_then(|c| c.hajimei).unwrap_or(false),
+                },
+            };
+            Box::new(Watermark {
+                source,
+                layout,
+                mode,
+                tile: TileLayout::from_config(config),
+                kiss,
+            })
+        }
+
+        // 10.1 海报模板
+        "template" => match config.and_then(|c| c.template.clone()) {
+            Some(template) => Box::new(Template { template, kiss }),
+            None => return skip_stage(name, "template", explicit),
+        },
+
+        // 11. 不可见来源标记 - 最后写入，之后不能再改动像素
+        "mark" => match config
+            .and_then(|c| c.mark_id.clone())
+            .filter(|id| !id.is_empty())
+        {
+            Some(id) => Box::new(ProvenanceMark { id }),
+            None => return skip_stage(name, "mark_id", explicit),
+        },
+
+        _ => {
+            return Err(OLIError::InvalidConfig(format!(
+                "unknown pipeline stage \"{name}\""
+            )));
+        }
+    };
+    Ok(Some(stage))
+}
+
+// 展开自定义流水线，得到各阶段的名称与使用的配置（阶段对象中的字段覆盖顶层配置）
+fn resolve_stages(
+    config: &OLIConfig,
+    definition: &PipelineDefinition,
+) -> Result<Vec<(String, OLIConfig)>, OLIError> {
+    let stages = match definition {
+        PipelineDefinition::Stages(stages) => Cow::Borrowed(stages),
+        PipelineDefinition::Toml(source) => Cow::Owned(parse_toml(source)?),
+    };
+
+    let Ok(Value::Object(mut base)) = serde_json::to_value(config) else {
+        return Err(OLIError::InvalidConfig(
+            "config cannot be used as a pipeline base".to_string(),
+        ));
+    };
+    base.remove("pipeline");
+
+    stages
+        .iter()
+        .enumerate()
+        .map(|(i, stage)| {
+            let name = stage.get("stage").and_then(Value::as_str).ok_or_else(|| {
+                OLIError::InvalidConfig(format!("pipeline stage {i} has no \"stage\" name"))
+            })?;
+
+            let mut merged = base.clone();
+            for (key, value) in stage.iter().filter(|(key, _)| *key != "stage") {
+                merged.insert(key.clone(), value.clone());
+            }
+            let stage_config = serde_json::from_value(Value::Object(merged))
+                .map_err(|e| OLIError::InvalidConfig(format!("pipeline stage \"{name}\": {e}")))?;
+            Ok((name.to_string(), stage_config))
+        })
+        .collect()
+}
+
+// TOML 格式的流水线：[[pipeline]] 表的数组，每个表与 JSON 中的阶段对象相同
+#[cfg(feature = "toml-pipeline")]
+fn parse_toml(source: &str) -> Result<Vec<Map<String, Value>>, OLIError> {
+    #[derive(Deserialize)]
+    struct TomlPipeline {
+        pipeline: Vec<Map<String, Value>>,
+    }
+
+    toml::from_str::<TomlPipeline>(source)
+        .map(|definition| definition.pipeline)
+        .map_err(|e| OLIError::InvalidConfig(format!("invalid pipeline TOML: {e}")))
+}
+
+#[cfg(not(feature = "toml-pipeline"))]
+fn parse_toml(_source: &str) -> Result<Vec<Map<String, Value>>, OLIError> {
+    Err(OLIError::InvalidConfig(
+        "TOML pipelines need the toml-pipeline feature".to_string(),
+    ))
+}
+
+// 线稿质量预设对应的均值卷积核半径，sketch 不做卷积
+// emboss 目前与 normal 相同
+fn quality_radius(quality: &str) -> Result<Option<f32>, OLIError> {
+    let size = match quality {
+        "fine" => 5,
+        "normal" | "emboss" => 7,
+        "coarse" => 9,
+        "superCoarse" => 11,
+        "extraCoarse" => 13,
+        "sketch" => return Ok(None),
+        _ => {
+            return Err(OLIError::InvalidConfig(format!(
+                "unknown quality \"{quality}\""
+            )));
+        }
+    };
+    Ok(Some(((size - 1) / 2) as f32))
+}
+
+// 高通滤波卷积核半径的上限（对应直径 129），非均值核的卷积核与半径的平方成正比
+const MAX_KERNEL_RADIUS: f32 = 64.0;
+
+// 高通滤波卷积核半径：kernel_radius 优先，其次 kernel_size（奇数），最后使用 quality 预设
+fn kernel_radius(config: Option<&OLIConfig>) -> Result<Option<f32>, OLIError> {
+    if let Some(radius) = config.and_then(|c| c.kernel_radius) {
+        if radius.is_nan() || radius <= 0.0 || radius > MAX_KERNEL_RADIUS {
+            return Err(OLIError::InvalidConfig(format!(
+                "kernel_radius must be in (0, {MAX_KERNEL_RADIUS}], got {radius}"
+            )));
+        }
+        return Ok(Some(radius));
+    }
+
+    if let Some(size) = config.and_then(|c| c.kernel_size) {
+        if size % 2 == 0 {
+            return Err(OLIError::InvalidConfig(format!(
+                "kernel_size must be odd, got {size}"
+            )));
+        }
+        if ((size - 1) / 2) as f32 > MAX_KERNEL_RADIUS {
+            return Err(OLIError::InvalidConfig(format!(
+                "kernel_size must be at most {}, got {size}",
+                2 * MAX_KERNEL_RADIUS as u32 + 1
+            )));
+        }
+        return Ok(Some(((size - 1) / 2) as f32));
+    }
+
+    quality_radius(
+        config
+            .and_then(|c| c.quality.as_deref())
+            .unwrap_or("normal"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    // 低质量JPEG的测试图：渐变背景上的深色圆圈与斜线，去块阶段会生效
+    fn sample_jpeg() -> Vec<u8> {
+        let img = RgbImage::from_fn(160, 120, |x, y| {
+            let (dx, dy) = (x as f32 - 80.0, y as f32 - 60.0);
+            let ring = ((dx * dx + dy * dy).sqrt() - 40.0).abs() < 3.0;
+            let stripe = (x + 2 * y) % 29 < 2;
+            if ring || stripe {
+                Rgb([30, 40, 60])
+            } else {
+                let v = (120 + x / 2 + y / 3) as u8;
+                Rgb([v, v - 10, v - 20])
+            }
+        });
+        let mut data = Vec::new();
+        img.write_with_encoder(JpegEncoder::new_with_quality(&mut data, 40))
+            .unwrap();
+        data
+    }
+
+    // default_output_is_pinned 的期望值
+    const GOLDEN_NO_WATERMARK: u64 = 0x3b3a_a80e_5680_2385;
+    #[cfg(feature = "bundled-watermark")]
+    const GOLDEN_DEFAULT: u64 = 0xdf19_7032_8d74_656b;
+
+    fn config(json: &str) -> OLIConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    // 默认配置下 from_config 应与手动按默认参数组装的阶段完全一致
+    #[test]
+    fn default_pipeline_matches_manual_stages() {
+        let input = sample_jpeg();
+        let default = Pipeline::from_config(None).unwrap();
+        assert_eq!(
+            default.names(),
+            [
+                "deblock",
+                "resize",
+                "gray",
+                "denoise",
+                "lines",
+                "cut",
+                "antialias",
+                "sharpen",
+                "colorize",
+                "composite",
+                "watermark",
+            ]
+        );
+
+        let manual = Pipeline::new(DecodeLimits::from_config(None))
+            .stage(Deblock {
+                enabled: None,
+                quality: 75,
+            })
+            .stage(Resize {
+                zoom: 1.0,
+                supersample: AntialiasMode::EdgeBlur.scale(),
+            })
+            .stage(Grayscale)
+            .stage(Denoise {
+                mode: DenoiseMode::Gaussian,
+                strength: 1.0,
+            })
+            .stage(LineExtraction {
+                extractor: LineExtractor::HighPass,
+                kernel: Some((KernelShape::Box, 3.0)),
+                params: XdogParams {
+                    sigma: 1.0,
+                    k: 1.6,
+                    p: 20.0,
+                    epsilon: 80.0,
+                    phi: 10.0,
+                },
+                canny_low: 40.0,
+                canny_high: 100.0,
+            })
+            .stage(Cut {
+                light_cut: 128.0,
+                dark_cut: 118.0,
+                mode: CutMode::Fixed,
+            })
+            .stage(Antialias {
+                mode: AntialiasMode::EdgeBlur,
+                threshold: AntialiasMode::EdgeBlur.default_threshold(),
+            })
+            .stage(Sharpen {
+                amount: 1.0,
+                radius: 0.9,
+                threshold: 0.0,
+            })
+            .stage(Colorize { kiss: true })
+            .stage(Composite)
+            .stage(Watermark {
+                source: WatermarkSource::Image {
+                    image: None,
+                    rows: None,
+                    cols: None,
+                    sprite: None,
+                    hajimei: false,
+                },
+                layout: WatermarkLayout::from_config(None).unwrap(),
+                mode: WatermarkMode::Single,
+                tile: TileLayout::from_config(None),
+                kiss: true,
+            });
+        assert_eq!(default.names(), manual.names());
+
+        let (expected, _) = default.run(&input).unwrap();
+        let (actual, _) = manual.run(&input).unwrap();
+        assert_eq!(expected, actual);
+
+        // 显式列出默认阶段的自定义流水线结果相同
+        let listed = config(
+            r#"{"pipeline": [
+                {"stage": "deblock"}, {"stage": "resize"}, {"stage": "gray"},
+                {"stage": "denoise"}, {"stage": "lines"}, {"stage": "cut"},
+                {"stage": "antialias"}, {"stage": "sharpen"}, {"stage": "colorize"},
+                {"stage": "composite"}, {"stage": "watermark"}
+            ]}"#,
+        );
+        let listed = Pipeline::from_config(Some(&listed)).unwrap();
+        assert_eq!(listed.names(), default.names());
+        assert_eq!(listed.run(&input).unwrap().0, expected);
+
+        // 输出尺寸与输入相同，多次执行结果一致
+        let output = image::load_from_memory(&expected).unwrap();
+        assert_eq!((output.width(), output.height()), (160, 120));
+        assert_eq!(default.run(&input).unwrap().0, expected);
+    }
+
+    // 输出像素的 FNV-1a 哈希，与 PNG 编码方式无关
+    fn pixel_hash(png: &[u8]) -> u64 {
+        let pixels = image::load_from_memory(png).unwrap().to_rgba8();
+        pixels
+            .as_raw()
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+
+    // 固定默认流程的输出：任何阶段的输出变化都会让哈希改变，需要确认后再更新这里的值
+    #[test]
+    fn default_output_is_pinned() {
+        let input = sample_jpeg();
+        let default = Pipeline::from_config(None).unwrap();
+        let (png, _) = default.remove("watermark").run(&input).unwrap();
+        assert_eq!(
+            pixel_hash(&png),
+            GOLDEN_NO_WATERMARK,
+            "{:#x}",
+            pixel_hash(&png)
+        );
+
+        #[cfg(feature = "bundled-watermark")]
+        {
+            let (png, _) = Pipeline::from_config(None).unwrap().run(&input).unwrap();
+            assert_eq!(pixel_hash(&png), GOLDEN_DEFAULT, "{:#x}", pixel_hash(&png));
+        }
+    }
+
+    // remove/insert/replace 调整后的流水线与对应配置构建的流水线一致
+    #[test]
+    fn edited_pipeline_matches_config() {
+        let input = sample_jpeg();
+        let edited = Pipeline::from_config(None)
+            .unwrap()
+            .remove("watermark")
+            .insert(3, Light { amount: 30.0 })
+            .replace("colorize", Colorize { kiss: false });
+        assert_eq!(edited.position("watermark"), None);
+        assert_eq!(edited.position("light"), Some(3));
+        assert_eq!(edited.position("colorize"), Some(9));
+
+        let configured = Pipeline::from_config(Some(&config(
+            r#"{"watermark": false, "light": 30, "kiss": false}"#,
+        )))
+        .unwrap();
+        assert_eq!(edited.names(), configured.names());
+        assert_eq!(
+            edited.run(&input).unwrap().0,
+            configured.run(&input).unwrap().0
+        );
+
+        // 不存在的阶段：replace 不做处理，insert 超出范围时添加到末尾
+        let names = edited.names().len();
+        let edited = edited
+            .replace("duotone", Grayscale)
+            .insert(usize::MAX, Composite);
+        assert_eq!(edited.names().len(), names + 1);
+        assert_eq!(edited.names().last(), Some(&"composite"));
+    }
+
+    // 自定义流水线中列出的阶段不受开关影响，缺少参数时报错而不是被丢弃
+    #[test]
+    fn listed_stages_are_never_dropped() {
+        let listed = config(
+            r#"{"watermark": false, "sharpen": false, "denoise": false, "pipeline": [
+                {"stage": "gray"}, {"stage": "denoise"}, {"stage": "sharpen"}, {"stage": "watermark"}
+            ]}"#,
+        );
+        assert_eq!(
+            Pipeline::from_config(Some(&listed)).unwrap().names(),
+            ["gray", "denoise", "sharpen", "watermark"]
+        );
+
+        for (stage, param) in [
+            ("light", "light"),
+            ("curve", "curve"),
+            ("contrast", "contrast"),
+            ("line_width", "line_width"),
+            ("output_curve", "output_curve"),
+            ("template", "template"),
+            ("mark", "mark_id"),
+        ] {
+            let listed = config(&format!(r#"{{"pipeline": [{{"stage": "{stage}"}}]}}"#));
+            let Err(OLIError::InvalidConfig(message)) = Pipeline::from_config(Some(&listed)) else {
+                panic!("stage {stage} should be rejected");
+            };
+            assert!(message.contains(&format!("\"{param}\"")), "{message}");
+        }
+
+        // 阶段中可以换用倍率相同的抗锯齿算法，不能改变超采样倍率
+        let listed = config(
+            r#"{"pipeline": [{"stage": "resize"}, {"stage": "antialias", "antialias": "smaa"}]}"#,
+        );
+        assert!(Pipeline::from_config(Some(&listed)).is_ok());
+        let listed = config(
+            r#"{"pipeline": [{"stage": "resize"}, {"stage": "antialias", "antialias": "ssaa"}]}"#,
+        );
+        assert!(matches!(
+            Pipeline::from_config(Some(&listed)),
+            Err(OLIError::InvalidConfig(_))
+        ));
+
+        let listed = config(r#"{"pipeline": [{"stage": "light", "light": 20}]}"#);
+        assert_eq!(
+            Pipeline::from_config(Some(&listed)).unwrap().names(),
+            ["light"]
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use image::RgbaImage;
+
+use crate::error::OLIError;
+
+// 不可见的来源标记：把一个短ID写进亮度通道 8×8 块的一个中频DCT系数里（量化索引调制），
+// 块网格与JPEG一致，PNG无损保存后完全可读，轻度JPEG重压缩后靠多次重复投票恢复
+
+const BLOCK: usize = 8;
+
+// 使用的DCT系数（水平频率, 竖直频率），JPEG在这个位置的量化步长较小
+const COEFF: (usize, usize) = (1, 2);
+
+// 量化间隔：越大越抗压缩，像素改动也越大（最大约 STEP/8）
+const STEP: f32 = 24.0;
+
+// 标记帧：1字节长度 + 最多13字节ID + 2字节CRC，共128位
+pub const MAX_MARK_LEN: usize = 13;
+const FRAME_BYTES: usize = 1 + MAX_MARK_LEN + 2;
+const FRAME_BITS: usize = FRAME_BYTES * 8;
+
+// 写入时的最大迭代次数：白色背景处只能往暗处改，需要多次逼近目标值
+const EMBED_PASSES: usize = 6;
+
+// 8点正交DCT基函数 basis[k][n]
+fn basis() -> [[f32; BLOCK]; BLOCK] {
+    let mut table = [[0.0f32; BLOCK]; BLOCK];
+    for (k, row) in table.iter_mut().enumerate() {
+        let scale = if k == 0 {
+            (1.0 / BLOCK as f32).sqrt()
+        } else {
+            (2.0 / BLOCK as f32).sqrt()
+        };
+        for (n, v) in row.iter_mut().enumerate() {
+            *v = scale
+                * ((2 * n + 1) as f32 * k as f32 * std::f32::consts::PI / (2 * BLOCK) as f32).cos();
+        }
+    }
+    table
+}
+
+// 每个块的抖动偏移（0-STEP），让各块的量化格点互不相同，写入后不会形成规则的纹理
+fn dither(block: usize) -> f32 {
+    let mut x = block as u32 ^ 0x4f4c_4931;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x as f32 / u32::MAX as f32 * STEP
+}
+
+// CRC-16/CCITT-FALSE
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn luma(img: &RgbaImage, x: u32, y: u32) -> f32 {
+    let p = img.get_pixel(x, y);
+    p[0] as f32 * 0.299 + p[1] as f32 * 0.587 + p[2] as f32 * 0.114
+}
+
+// 块 (bx, by) 亮度的 COEFF 系数
+fn coefficient(img: &RgbaImage, basis: &[[f32; BLOCK]; BLOCK], bx: u32, by: u32) -> f32 {
+    let (u, v) = COEFF;
+    let mut sum = 0.0;
+    for y in 0..BLOCK {
+        for x in 0..BLOCK {
+            let value = luma(
+                img,
+                bx * BLOCK as u32 + x as u32,
+                by * BLOCK as u32 + y as u32,
+            );
+            sum += value * basis[u][x] * basis[v][y];
+        }
+    }
+    sum
+}
+
+// 在图片上写入ID（UTF-8 最多 MAX_MARK_LEN 字节）；图片小于 128 个块时无法容纳，不做处理
+pub fn embed_mark(img: &mut RgbaImage, id: &str) -> Result<(), OLIError> {
+    let id = id.as_bytes();
+    if id.len() > MAX_MARK_LEN {
+        return Err(OLIError::InvalidConfig(format!(
+            "mark_id must be at most {MAX_MARK_LEN} bytes, got {}",
+            id.len()
+        )));
+    }
+
+    let blocks_x = img.width() / BLOCK as u32;
+    let blocks_y = img.height() / BLOCK as u32;
+    if ((blocks_x * blocks_y) as usize) < FRAME_BITS {
+        return Ok(());
+    }
+
+    let mut frame = [0u8; FRAME_BYTES];
+    frame[0] = id.len() as u8;
+    frame[1..1 + id.len()].copy_from_slice(id);
+    let crc = crc16(&frame[..FRAME_BYTES - 2]);
+    frame[FRAME_BYTES - 2..].copy_from_slice(&crc.to_be_bytes());
+
+    let basis = basis();
+    let (u, v) = COEFF;
+    for by in 0..blocks_y {
+        for bx in 0..blocks_x {
+            let block = (by * blocks_x + bx) as usize;
+            let bit_index = block % FRAME_BITS;
+            let bit = (frame[bit_index / 8] >> (7 - bit_index % 8)) & 1;
+
+            // 目标：离当前系数最近的、属于该比特的格点（0: d + kΔ，1: d + kΔ + Δ/2）
+            let offset = dither(block) + bit as f32 * STEP / 2.0;
+            let current = coefficient(img, &basis, bx, by);
+            let target = ((current - offset) / STEP).round() * STEP + offset;
+
+            for _ in 0..EMBED_PASSES {
+                let delta = target - coefficient(img, &basis, bx, by);
+                if delta.abs() < STEP / 16.0 {
+                    break;
+                }
+                // RGB加上相同的值时亮度改变同样的量
+                for y in 0..BLOCK {
+                    for x in 0..BLOCK {
+                        let change = delta * basis[u][x] * basis[v][y];
+                        let p = img.get_pixel_mut(
+                            bx * BLOCK as u32 + x as u32,
+                            by * BLOCK as u32 + y as u32,
+                        );
+                        for c in 0..3 {
+                            p[c] = (p[c] as f32 + change).round().clamp(0.0, 255.0) as u8;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// 读取 embed_mark 写入的ID，没有标记或标记已损坏（CRC不符）时返回 None
+pub fn detect_mark(img: &RgbaImage) -> Option<String> {
+    let blocks_x = img.width() / BLOCK as u32;
+    let blocks_y = img.height() / BLOCK as u32;
+    if ((blocks_x * blocks_y) as usize) < FRAME_BITS {
+        return None;
+    }
+
+    // 软判决投票：系数落在 0 的格点上记 +1，落在 1 的格点上记 -1
+    let basis = basis();
+    let mut votes = [0.0f32; FRAME_BITS];
+    for by in 0..blocks_y {
+        for bx in 0..blocks_x {
+            let block = (by * blocks_x + bx) as usize;
+            let phase = (coefficient(img, &basis, bx, by) - dither(block)) / STEP;
+            votes[block % FRAME_BITS] += (phase * std::f32::consts::TAU).cos();
+        }
+    }
+
+    let mut frame = [0u8; FRAME_BYTES];
+    for (i, vote) in votes.iter().enumerate() {
+        if *vote < 0.0 {
+            frame[i / 8] |= 1 << (7 - i % 8);
+        }
+    }
+
+    let crc = u16::from_be_bytes([frame[FRAME_BYTES - 2], frame[FRAME_BYTES - 1]]);
+    let len = frame[0] as usize;
+    if crc != crc16(&frame[..FRAME_BYTES - 2]) || len > MAX_MARK_LEN {
+        return None;
+    }
+    String::from_utf8(frame[1..1 + len].to_vec()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{DynamicImage, ImageFormat, Rgba};
+
+    use super::*;
+
+    const ID: &str = "bot-42";
+
+    // 与输出相近的测试图：白底上的深色斜线与圆圈
+    fn line_art() -> RgbaImage {
+        RgbaImage::from_fn(320, 240, |x, y| {
+            let (dx, dy) = (x as f32 - 160.0, y as f32 - 120.0);
+            let ring = ((dx * dx + dy * dy).sqrt() - 80.0).abs() < 2.0;
+            let stripe = (x + 2 * y) % 37 < 2;
+            if ring || stripe {
+                Rgba([40, 40, 40, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        })
+    }
+
+    // 8 位随机噪声，最难保留标记的情况
+    fn noise() -> RgbaImage {
+        let mut state = 0x9e37_79b9_u32;
+        RgbaImage::from_fn(320, 240, |_, _| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let v = (state >> 24) as u8;
+            Rgba([v, v, v, 255])
+        })
+    }
+
+    fn round_trip(img: &RgbaImage, format: ImageFormat) -> RgbaImage {
+        let mut data = Vec::new();
+        match format {
+            ImageFormat::Jpeg => {
+                let rgb = DynamicImage::ImageRgba8(img.clone()).to_rgb8();
+                rgb.write_with_encoder(JpegEncoder::new_with_quality(&mut data, 85))
+                    .unwrap();
+            }
+            _ => img.write_to(&mut Cursor::new(&mut data), format).unwrap(),
+        }
+        image::load_from_memory(&data).unwrap().to_rgba8()
+    }
+
+    #[test]
+    fn survives_png_and_jpeg() {
+        for (name, mut img) in [("line art", line_art()), ("noise", noise())] {
+            embed_mark(&mut img, ID).unwrap();
+            assert_eq!(detect_mark(&img).as_deref(), Some(ID), "{name}");
+            assert_eq!(
+                detect_mark(&round_trip(&img, ImageFormat::Png)).as_deref(),
+                Some(ID),
+                "{name} after PNG"
+            );
+            assert_eq!(
+                detect_mark(&round_trip(&img, ImageFormat::Jpeg)).as_deref(),
+                Some(ID),
+                "{name} after JPEG q85"
+            );
+        }
+    }
+
+    #[test]
+    fn unmarked_images_have_no_mark() {
+        for img in [line_art(), noise()] {
+            assert_eq!(detect_mark(&img), None);
+            assert_eq!(detect_mark(&round_trip(&img, ImageFormat::Jpeg)), None);
+        }
+    }
+
+    #[test]
+    fn rejects_long_ids() {
+        let mut img = line_art();
+        assert!(embed_mark(&mut img, "a-mark-id-that-is-too-long").is_err());
+    }
+}
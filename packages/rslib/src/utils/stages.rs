@@ -0,0 +1,728 @@
+use std::borrow::Cow;
+
+use ab_glyph::Font;
+use base64::Engine;
+use image::{GenericImageView, GrayImage, Rgba, RgbaImage};
+
+use super::antialias::{AntialiasMode, antialias, antialias_maps, downsample};
+use super::contrast::{ContrastMode, auto_cut, auto_levels, equalize_local, ink_coverage};
+use super::convolution::{KernelShape, convolve_ndarray};
+use super::curves::Curve;
+use super::deblock::{DEFAULT_DC_STEP, deblock};
+use super::decode::{DecodeLimits, load_image};
+use super::denoise::{DenoiseMode, denoise};
+use super::image_processing::{colorize_text, get_gradient_color, unsharp_mask};
+use super::line_extraction::{LineExtractor, XdogParams, canny, dog, xdog};
+use super::morphology::{StructuringElement, adjust_line_width};
+use super::pipeline::{Frame, Stage, gray_image};
+use super::provenance::embed_mark;
+use super::template::compose;
+use super::text::{TextAlign, bundled_font, fit_text_size, load_font, rasterize_text};
+use super::watermark::{
+    BUNDLED_WATERMARK_ROWS, TileLayout, WatermarkLayout, WatermarkMode, bundled_watermark,
+    fit_watermark, place_watermark, sprite, sprite_count, text_size, tile_watermark,
+};
+use crate::TemplateConfig;
+use crate::error::OLIError;
+
+// JPEG去块：enabled 不设置时对估算质量低于 quality 的JPEG自动启用
+pub struct Deblock {
+    pub enabled: Option<bool>,
+    pub quality: u32,
+}
+
+impl Stage for Deblock {
+    fn name(&self) -> &str {
+        "deblock"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        let enabled = self.enabled.unwrap_or(
+            frame
+                .jpeg_quality
+                .as_ref()
+                .is_some_and(|q| q.quality < self.quality),
+        );
+        if enabled {
+            // 去块在原始分辨率上进行（RGBA副本、三个浮点通道与双边滤波缓冲区），先于缩放阶段检查内存；
+            // 自动启用时超出限制就跳过，只有明确设置 deblock: true 时才报错
+            let (width, height) = frame.source.dimensions();
+            if let Err(err) = frame.limits.check_working_set(width, height) {
+                return match self.enabled {
+                    Some(true) => Err(err),
+                    _ => Ok(()),
+                };
+            }
+            let dc_step = frame
+                .jpeg_quality
+                .as_ref()
+                .map_or(DEFAULT_DC_STEP, |q| q.dc_step);
+            frame.source = deblock(&frame.source, dc_step);
+        }
+        Ok(())
+    }
+}
+
+// 缩放到处理尺寸：原图尺寸除以 zoom，再乘以超采样倍率 supersample
+pub struct Resize {
+    pub zoom: f32,
+    pub supersample: u32,
+}
+
+impl Stage for Resize {
+    fn name(&self) -> &str {
+        "resize"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        let (ori_w, ori_h) = frame.source.dimensions();
+        let width = (ori_w as f32 / self.zoom).round() as u32;
+        let height = (ori_h as f32 / self.zoom).round() as u32;
+        let scale = self.supersample.max(1);
+        let (work_w, work_h) = (width * scale, height * scale);
+        frame.limits.check_working_set(work_w, work_h)?;
+
+        // 使用Lanczos3获得更锐利的边缘
+        frame.resized = image::imageops::resize(
+            &frame.source,
+            work_w,
+            work_h,
+            image::imageops::FilterType::Lanczos3,
+        );
+        frame.width = work_w;
+        frame.height = work_h;
+        frame.scale = scale;
+        Ok(())
+    }
+}
+
+// 转灰度：之后各阶段都在浮点缓冲区上进行，只在着色时量化；没有缩放阶段时使用原图
+pub struct Grayscale;
+
+impl Stage for Grayscale {
+    fn name(&self) -> &str {
+        "gray"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        let resized = if frame.resized.width() > 0 {
+            Cow::Borrowed(&frame.resized)
+        } else {
+            frame
+                .limits
+                .check_working_set(frame.source.width(), frame.source.height())?;
+            Cow::Owned(frame.source.to_rgba8())
+        };
+        let (w, h) = resized.dimensions();
+
+        let mut gray = vec![0.0f32; (w * h) as usize];
+        for y in 0..h {
+            for x in 0..w {
+                let pixel = resized.get_pixel(x, y);
+                let r = pixel[0] as f32;
+                let g = pixel[1] as f32;
+                let b = pixel[2] as f32;
+                gray[(y * w + x) as usize] = r * 0.299 + g * 0.587 + b * 0.114;
+            }
+        }
+
+        frame.pixels = gray;
+        frame.width = w;
+        frame.height = h;
+        Ok(())
+    }
+}
+
+// 亮度调整（百分比）
+pub struct Light {
+    pub amount: f32,
+}
+
+impl Stage for Light {
+    fn name(&self) -> &str {
+        "light"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        for val in frame.pixels.iter_mut() {
+            *val = (*val + *val * (self.amount / 100.0)).clamp(0.0, 255.0);
+        }
+        Ok(())
+    }
+}
+
+// 色调曲线：作用于提取线稿前的灰度图（curve）或着色前的线稿（output_curve）
+pub struct ToneCurve {
+    pub curve: Curve,
+    output: bool,
+}
+
+impl ToneCurve {
+    pub fn input(curve: Curve) -> Self {
+        ToneCurve {
+            curve,
+            output: false,
+        }
+    }
+
+    pub fn output(curve: Curve) -> Self {
+        ToneCurve {
+            curve,
+            output: true,
+        }
+    }
+}
+
+impl Stage for ToneCurve {
+    fn name(&self) -> &str {
+        if self.output { "output_curve" } else { "curve" }
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        self.curve.apply_all(&mut frame.pixels);
+        Ok(())
+    }
+}
+
+// 去噪，半径随超采样倍率放大
+pub struct Denoise {
+    pub mode: DenoiseMode,
+    pub strength: f32,
+}
+
+impl Stage for Denoise {
+    fn name(&self) -> &str {
+        "denoise"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        frame.pixels = denoise(
+            &frame.pixels,
+            frame.width,
+            frame.height,
+            self.mode,
+            self.strength,
+            frame.scale,
+        );
+        Ok(())
+    }
+}
+
+// 局部对比度增强：逆光或昏暗的照片先做自适应直方图均衡
+pub struct Contrast {
+    pub mode: ContrastMode,
+    pub tiles: u32,
+    pub clip: f32,
+}
+
+impl Stage for Contrast {
+    fn name(&self) -> &str {
+        "contrast"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        let (w, h) = (frame.width, frame.height);
+        match self.mode {
+            ContrastMode::None => {}
+            ContrastMode::Clahe => {
+                frame.pixels = equalize_local(&frame.pixels, w, h, self.tiles, Some(self.clip));
+            }
+            ContrastMode::Local => {
+                frame.pixels = equalize_local(&frame.pixels, w, h, self.tiles, None);
+            }
+        }
+        Ok(())
+    }
+}
+
+// 提取线稿：kernel 为高通滤波的卷积核形状与半径（None 时不做卷积，即 sketch），
+// params 中的 sigma 与 k 同时用于 dog，canny_low/canny_high 为梯度幅值阈值
+// 超采样时按倍率放大卷积核半径与 sigma，梯度幅值按倍率缩小
+pub struct LineExtraction {
+    pub extractor: LineExtractor,
+    pub kernel: Option<(KernelShape, f32)>,
+    pub params: XdogParams,
+    pub canny_low: f32,
+    pub canny_high: f32,
+}
+
+impl Stage for LineExtraction {
+    fn name(&self) -> &str {
+        "lines"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        let (w, h) = (frame.width, frame.height);
+        let scale = frame.scale as f32;
+        let sigma = self.params.sigma * scale;
+        let kernel = self
+            .kernel
+            .map(|(shape, radius)| shape.build(radius * scale));
+
+        let gray = &frame.pixels;
+        let processed = match self.extractor {
+            LineExtractor::HighPass => match kernel {
+                Some(ref kernel) => {
+                    let convolved = convolve_ndarray(gray, w, h, kernel);
+
+                    // 高通滤波: 128 + 原始 - 卷积
+                    let mut diff = vec![0.0f32; (w * h) as usize];
+                    for i in 0..diff.len() {
+                        diff[i] = 128.0 + gray[i] - convolved[i];
+                    }
+                    diff
+                }
+                None => gray.clone(),
+            },
+            LineExtractor::Dog => dog(gray, w, h, sigma, self.params.k),
+            LineExtractor::Xdog => xdog(
+                gray,
+                w,
+                h,
+                &XdogParams {
+                    sigma,
+                    ..self.params
+                },
+            ),
+            LineExtractor::Canny => canny(
+                gray,
+                w,
+                h,
+                sigma,
+                self.canny_low / scale,
+                self.canny_high / scale,
+            ),
+        };
+
+        // 高通滤波（sketch 除外）与 DoG 的结果以128为中心，需要拉伸
+        frame.needs_cut = self.extractor.needs_cut()
+            && (self.extractor != LineExtractor::HighPass || kernel.is_some());
+        frame.pixels = processed;
+        Ok(())
+    }
+}
+
+// light_cut/dark_cut 的来源
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CutMode {
+    // 使用配置的值
+    Fixed,
+    // 根据直方图自动选择
+    AutoLevels,
+    // 按目标墨迹覆盖率（百分比）自动选择
+    AutoCut(f32),
+}
+
+// lightCut/darkCut：把以128为中心的线稿拉伸到 0-255，不以128为中心的线稿不做处理
+pub struct Cut {
+    pub light_cut: f32,
+    pub dark_cut: f32,
+    pub mode: CutMode,
+}
+
+impl Stage for Cut {
+    fn name(&self) -> &str {
+        "cut"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        if !frame.needs_cut {
+            return Ok(());
+        }
+
+        let (light_cut, dark_cut) = match self.mode {
+            CutMode::Fixed => (self.light_cut, self.dark_cut),
+            CutMode::AutoLevels => auto_levels(&frame.pixels),
+            CutMode::AutoCut(coverage) => {
+                let band = 255.0 - self.light_cut - self.dark_cut;
+                auto_cut(&frame.pixels, coverage / 100.0, band)
+            }
+        };
+        if light_cut > 0.0 || dark_cut > 0.0 {
+            let scale = 255.0 / (255.0 - light_cut - dark_cut);
+            for val in frame.pixels.iter_mut() {
+                *val = ((*val - dark_cut) * scale).clamp(0.0, 255.0);
+            }
+        }
+
+        frame.light_cut = Some(light_cut);
+        frame.dark_cut = Some(dark_cut);
+        Ok(())
+    }
+}
+
+// 线条粗细：腐蚀加粗、膨胀变细（超采样时按倍率放大半径）
+pub struct LineWidth {
+    pub width: f32,
+    pub shape: StructuringElement,
+}
+
+impl Stage for LineWidth {
+    fn name(&self) -> &str {
+        "line_width"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        frame.pixels = adjust_line_width(
+            &frame.pixels,
+            frame.width,
+            frame.height,
+            self.width * frame.scale as f32,
+            self.shape,
+        );
+        Ok(())
+    }
+}
+
+// 抗锯齿，之后把超采样的缓冲区缩小回输出尺寸
+pub struct Antialias {
+    pub mode: AntialiasMode,
+    pub threshold: f32,
+}
+
+impl Stage for Antialias {
+    fn name(&self) -> &str {
+        "antialias"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        let (w, h) = (frame.width, frame.height);
+        if frame.debug.is_some()
+            && let Some((edges, weights)) =
+                antialias_maps(&frame.pixels, w, h, self.mode, self.threshold)
+        {
+            frame.record("antialias_edges", || gray_image(&edges, w, h));
+            frame.record("antialias_weights", || gray_image(&weights, w, h));
+        }
+
+        let mut pixels = match self.mode {
+            // 超采样本身就是缩小，倍率取缩放阶段实际使用的值
+            AntialiasMode::Ssaa => std::mem::take(&mut frame.pixels),
+            mode => antialias(&frame.pixels, w, h, mode, self.threshold),
+        };
+
+        if frame.scale > 1 {
+            pixels = downsample(&pixels, w, h, frame.scale);
+            frame.width = w / frame.scale;
+            frame.height = h / frame.scale;
+            frame.scale = 1;
+        }
+        frame.pixels = pixels;
+        Ok(())
+    }
+}
+
+// Unsharp Mask 锐化：radius 为高斯sigma，与模糊结果相差小于 threshold 的像素保持不变
+pub struct Sharpen {
+    pub amount: f32,
+    pub radius: f32,
+    pub threshold: f32,
+}
+
+impl Stage for Sharpen {
+    fn name(&self) -> &str {
+        "sharpen"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        frame.pixels = unsharp_mask(
+            &frame.pixels,
+            frame.width,
+            frame.height,
+            self.amount,
+            self.radius,
+            self.threshold,
+        );
+        Ok(())
+    }
+}
+
+// 生成RGBA图像并在这里统一量化为8位：kiss 时为沿对角线的彩色渐变、越暗越不透明，否则为灰度
+// 同时记录墨迹覆盖率
+pub struct Colorize {
+    pub kiss: bool,
+}
+
+impl Stage for Colorize {
+    fn name(&self) -> &str {
+        "colorize"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        let (width, height) = (frame.width, frame.height);
+        frame.ink_coverage = ink_coverage(&frame.pixels) * 100.0;
+
+        let processed: Vec<u8> = frame
+            .pixels
+            .iter()
+            .map(|&v| v.round().clamp(0.0, 255.0) as u8)
+            .collect();
+        let mut rgba = RgbaImage::new(width, height);
+
+        if self.kiss {
+            // Kiss模式：彩色渐变
+            for y in 0..height {
+                for x in 0..width {
+                    let val = processed[(y * width + x) as usize];
+
+                    // 计算渐变位置
+                    let t = ((x as f32 + y as f32) / (width as f32 + height as f32)).min(1.0);
+
+                    // 从渐变色表获取颜色
+                    let (r, g, b) = get_gradient_color(t);
+
+                    // alpha = 255 - y（越暗越不透明）
+                    let alpha = (255.0 - val as f32) as u8;
+
+                    rgba.put_pixel(x, y, Rgba([r, g, b, alpha]));
+                }
+            }
+        } else {
+            // 灰度模式
+            for y in 0..height {
+                for x in 0..width {
+                    let val = processed[(y * width + x) as usize];
+                    rgba.put_pixel(x, y, Rgba([val, val, val, 255]));
+                }
+            }
+        }
+
+        frame.image = rgba;
+        Ok(())
+    }
+}
+
+// 双色调：按亮度在暗部与亮部颜色之间插值，代替 colorize，同样记录墨迹覆盖率
+pub struct Duotone {
+    pub dark: Rgba<u8>,
+    pub light: Rgba<u8>,
+}
+
+impl Stage for Duotone {
+    fn name(&self) -> &str {
+        "duotone"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        let (width, height) = (frame.width, frame.height);
+        frame.ink_coverage = ink_coverage(&frame.pixels) * 100.0;
+
+        let pixels = &frame.pixels;
+        frame.image = RgbaImage::from_fn(width, height, |x, y| {
+            let t = pixels[(y * width + x) as usize].round().clamp(0.0, 255.0) / 255.0;
+            Rgba(std::array::from_fn(|c| {
+                (self.dark[c] as f32 + (self.light[c] as f32 - self.dark[c] as f32) * t).round()
+                    as u8
+            }))
+        });
+        Ok(())
+    }
+}
+
+// 白色背景合成
+pub struct Composite;
+
+impl Stage for Composite {
+    fn name(&self) -> &str {
+        "composite"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        frame.materialize();
+        let (width, height) = frame.image.dimensions();
+        let mut final_img = RgbaImage::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let src = frame.image.get_pixel(x, y);
+                let alpha = src[3] as f32 / 255.0;
+                let inv_alpha = 1.0 - alpha;
+
+                let r = (src[0] as f32 * alpha + 255.0 * inv_alpha).min(255.0) as u8;
+                let g = (src[1] as f32 * alpha + 255.0 * inv_alpha).min(255.0) as u8;
+                let b = (src[2] as f32 * alpha + 255.0 * inv_alpha).min(255.0) as u8;
+
+                final_img.put_pixel(x, y, Rgba([r, g, b, 255]));
+            }
+        }
+
+        frame.image = final_img;
+        Ok(())
+    }
+}
+
+// 水印内容
+pub enum WatermarkSource {
+    // 文字水印，font 为 base64 编码的字体，没有时使用内置字体
+    Text {
+        text: String,
+        font: Option<String>,
+    },
+    // 图片水印（精灵图），image 为 base64 编码的图片，没有时使用内置水印；
+    // rows/cols 不设置时内置水印为 2 行 1 列、自定义水印为整张图，sprite 不设置时 hajimei 选第二格
+    Image {
+        image: Option<String>,
+        rows: Option<u32>,
+        cols: Option<u32>,
+        sprite: Option<u32>,
+        hajimei: bool,
+    },
+}
+
+// 用 font_b64 指定的字体（没有时用内置字体）渲染文字水印，字体无法解析时返回 None
+// 字号按 text_size 计算，文字超出画面时缩小到能放进画面
+fn text_watermark(
+    text: &str,
+    font_b64: Option<&str>,
+    width: u32,
+    height: u32,
+    scale: f32,
+    kiss: bool,
+) -> Option<RgbaImage> {
+    fn render<F: Font>(
+        font: &F,
+        text: &str,
+        width: u32,
+        height: u32,
+        scale: f32,
+    ) -> Option<GrayImage> {
+        let size = fit_text_size(font, text, text_size(width, height, scale), width, height);
+        (size >= 1.0).then(|| rasterize_text(font, text, size, TextAlign::Left))
+    }
+
+    if text_size(width, height, scale) < 1.0 {
+        return None;
+    }
+    let mask = match font_b64 {
+        Some(font_b64) => {
+            let font = base64::engine::general_purpose::STANDARD
+                .decode(font_b64)
+                .ok()
+                .and_then(load_font)?;
+            render(&font, text, width, height, scale)?
+        }
+        None => render(bundled_font()?, text, width, height, scale)?,
+    };
+    Some(colorize_text(&mask, kiss))
+}
+
+// 取出要使用的图片水印：image 无法解码时返回 None，没有提供时使用内置水印
+fn image_watermark(
+    image: Option<&str>,
+    rows: Option<u32>,
+    cols: Option<u32>,
+    index: Option<u32>,
+    hajimei: bool,
+    limits: &DecodeLimits,
+) -> Result<Option<RgbaImage>, OLIError> {
+    let sheet = match image {
+        Some(wm_b64) => base64::engine::general_purpose::STANDARD
+            .decode(wm_b64)
+            .ok()
+            .and_then(|wm_bytes| load_image(&wm_bytes, limits).ok())
+            .map(|wm_img| (Cow::Owned(wm_img.to_rgba8()), 1)),
+        None => bundled_watermark().map(|wm| (Cow::Borrowed(wm), BUNDLED_WATERMARK_ROWS)),
+    };
+    let Some((sheet, default_rows)) = sheet else {
+        return Ok(None);
+    };
+
+    let rows = rows.unwrap_or(default_rows);
+    let cols = cols.unwrap_or(1);
+    let index = index.unwrap_or(if hajimei {
+        1.min(sprite_count(rows, cols)?.saturating_sub(1))
+    } else {
+        0
+    });
+
+    sprite(sheet.as_ref(), rows, cols, index).map(Some)
+}
+
+// 水印：平铺模式先铺满画面，再在上面绘制单个水印；提供的字体或图片无法解码时不绘制
+pub struct Watermark {
+    pub source: WatermarkSource,
+    pub layout: WatermarkLayout,
+    pub mode: WatermarkMode,
+    pub tile: TileLayout,
+    // 文字水印的颜色：渐变色或深灰
+    pub kiss: bool,
+}
+
+impl Stage for Watermark {
+    fn name(&self) -> &str {
+        "watermark"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        frame.materialize();
+        let (width, height) = frame.image.dimensions();
+        let mark = match &self.source {
+            WatermarkSource::Text { text, font } => text_watermark(
+                text,
+                font.as_deref(),
+                width,
+                height,
+                self.layout.scale,
+                self.kiss,
+            ),
+            WatermarkSource::Image {
+                image,
+                rows,
+                cols,
+                sprite,
+                hajimei,
+            } => image_watermark(
+                image.as_deref(),
+                *rows,
+                *cols,
+                *sprite,
+                *hajimei,
+                &frame.limits,
+            )?
+            .and_then(|mark| fit_watermark(width, height, &mark, self.layout.scale)),
+        };
+
+        if let Some(mark) = mark {
+            if self.mode.tiles() {
+                tile_watermark(&mut frame.image, &mark, &self.tile);
+            }
+            if self.mode.single() {
+                place_watermark(&mut frame.image, &mark, &self.layout);
+            }
+        }
+        Ok(())
+    }
+}
+
+// 海报模板：把线稿放进画布，叠加图片、文字与边框
+pub struct Template {
+    pub template: TemplateConfig,
+    pub kiss: bool,
+}
+
+impl Stage for Template {
+    fn name(&self) -> &str {
+        "template"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        frame.materialize();
+        frame.image = compose(&frame.image, &self.template, self.kiss, &frame.limits)?;
+        Ok(())
+    }
+}
+
+// 不可见来源标记：应当放在最后，之后改动像素会破坏标记
+pub struct ProvenanceMark {
+    pub id: String,
+}
+
+impl Stage for ProvenanceMark {
+    fn name(&self) -> &str {
+        "mark"
+    }
+
+    fn apply(&self, frame: &mut Frame) -> Result<(), OLIError> {
+        frame.materialize();
+        embed_mark(&mut frame.image, &self.id)
+    }
+}
@@ -0,0 +1,250 @@
+use ab_glyph::Font;
+use base64::Engine;
+use image::{GrayImage, Rgba, RgbaImage};
+
+use super::decode::{DecodeLimits, load_image};
+use super::image_processing::colorize_text;
+use super::text::{TextAlign, bundled_font, colorize, fit_text_size, load_font, rasterize_text};
+use super::watermark::blend;
+use crate::error::OLIError;
+use crate::{TemplateConfig, TemplateLayer, TemplateRect};
+
+// 图片放进矩形区域的方式
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Fit {
+    // 保持比例完整放入，居中留白
+    Contain,
+    // 保持比例铺满，居中裁掉多余部分
+    Cover,
+    // 拉伸到区域大小
+    Stretch,
+}
+
+impl Fit {
+    pub fn from_name(name: &str) -> Result<Self, OLIError> {
+        match name {
+            "contain" => Ok(Fit::Contain),
+            "cover" => Ok(Fit::Cover),
+            "stretch" => Ok(Fit::Stretch),
+            _ => Err(OLIError::InvalidConfig(format!("unknown fit \"{name}\""))),
+        }
+    }
+}
+
+// 解析 #rrggbb 或 #rrggbbaa
+pub fn parse_color(color: &str) -> Result<Rgba<u8>, OLIError> {
+    let invalid = || OLIError::InvalidConfig(format!("invalid color \"{color}\""));
+    let hex = color.strip_prefix('#').ok_or_else(invalid)?;
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+    let alpha = if hex.len() == 8 { channel(6)? } else { 255 };
+    Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, alpha]))
+}
+
+// 按 fit 把图片缩放到矩形区域内，返回缩放后的图片与相对区域左上角的偏移
+fn fit_into(img: &RgbaImage, width: u32, height: u32, fit: Fit) -> (RgbaImage, i64, i64) {
+    let (w, h) = img.dimensions();
+    if w == 0 || h == 0 || width == 0 || height == 0 {
+        return (RgbaImage::new(0, 0), 0, 0);
+    }
+
+    let filter = image::imageops::FilterType::Lanczos3;
+    match fit {
+        Fit::Stretch => (image::imageops::resize(img, width, height, filter), 0, 0),
+        Fit::Contain => {
+            let scale = (width as f32 / w as f32).min(height as f32 / h as f32);
+            let new_w = ((w as f32 * scale).round() as u32).clamp(1, width);
+            let new_h = ((h as f32 * scale).round() as u32).clamp(1, height);
+            (
+                image::imageops::resize(img, new_w, new_h, filter),
+                (width - new_w) as i64 / 2,
+                (height - new_h) as i64 / 2,
+            )
+        }
+        Fit::Cover => {
+            let scale = (width as f32 / w as f32).max(height as f32 / h as f32);
+            let new_w = ((w as f32 * scale).round() as u32).max(width);
+            let new_h = ((h as f32 * scale).round() as u32).max(height);
+            let resized = image::imageops::resize(img, new_w, new_h, filter);
+            let cropped = image::imageops::crop_imm(
+                &resized,
+                (new_w - width) / 2,
+                (new_h - height) / 2,
+                width,
+                height,
+            )
+            .to_image();
+            (cropped, 0, 0)
+        }
+    }
+}
+
+// 把图片按 fit 放进矩形区域，区域同样受解码限制约束
+fn draw_fitted(
+    canvas: &mut RgbaImage,
+    img: &RgbaImage,
+    rect: &TemplateRect,
+    fit: Fit,
+    opacity: f32,
+    limits: &DecodeLimits,
+) -> Result<(), OLIError> {
+    limits.check_working_set(rect.width, rect.height)?;
+    let (fitted, dx, dy) = fit_into(img, rect.width, rect.height, fit);
+    blend(
+        canvas,
+        &fitted,
+        rect.x as i64 + dx,
+        rect.y as i64 + dy,
+        opacity,
+    );
+    Ok(())
+}
+
+// 栅格化文字图层：文字超出 max_width×max_height 时缩小到能放进去，过大的字号不会在栅格化时分配大量内存
+fn render_text<F: Font>(
+    font: &F,
+    text: &str,
+    size: f32,
+    align: TextAlign,
+    (max_width, max_height): (u32, u32),
+) -> GrayImage {
+    let size = fit_text_size(font, text, size, max_width, max_height);
+    rasterize_text(font, text, size, align)
+}
+
+// 把线稿放进模板：先铺背景色，再按 fit 放入线稿，最后按顺序绘制各图层
+// 图层中的图片或字体无法解码时跳过该图层
+pub fn compose(
+    drawing: &RgbaImage,
+    template: &TemplateConfig,
+    kiss: bool,
+    limits: &DecodeLimits,
+) -> Result<RgbaImage, OLIError> {
+    if template.width == 0 || template.height == 0 {
+        return Err(OLIError::InvalidConfig(format!(
+            "template size must be positive, got {}x{}",
+            template.width, template.height
+        )));
+    }
+    limits.check_working_set(template.width, template.height)?;
+
+    let background = match &template.background {
+        Some(color) => parse_color(color)?,
+        None => Rgba([255, 255, 255, 255]),
+    };
+    let mut canvas = RgbaImage::from_pixel(template.width, template.height, background);
+
+    let rect = template.drawing.unwrap_or(TemplateRect {
+        x: 0,
+        y: 0,
+        width: template.width,
+        height: template.height,
+    });
+    let fit = Fit::from_name(template.fit.as_deref().unwrap_or("contain"))?;
+    draw_fitted(&mut canvas, drawing, &rect, fit, 1.0, limits)?;
+
+    for layer in template.layers.iter().flatten() {
+        match layer {
+            TemplateLayer::Image {
+                image,
+                rect,
+                fit,
+                opacity,
+            } => {
+                let fit = Fit::from_name(fit.as_deref().unwrap_or("contain"))?;
+                let opacity = opacity.unwrap_or(1.0).clamp(0.0, 1.0);
+                let overlay = base64::engine::general_purpose::STANDARD
+                    .decode(image)
+                    .ok()
+                    .and_then(|bytes| load_image(&bytes, limits).ok());
+                if let Some(overlay) = overlay {
+                    draw_fitted(&mut canvas, &overlay.to_rgba8(), rect, fit, opacity, limits)?;
+                }
+            }
+            TemplateLayer::Text {
+                text,
+                x,
+                y,
+                width,
+                size,
+                color,
+                align,
+                font,
+            } => {
+                let align = TextAlign::from_name(align.as_deref().unwrap_or("left"))?;
+                let solid = match color.as_deref() {
+                    None | Some("kiss") => None,
+                    Some(color) => Some(parse_color(color)?),
+                };
+                let gradient = kiss || color.as_deref() == Some("kiss");
+                if *size < 1.0 {
+                    continue;
+                }
+                // 设置了 width 时文字不能超出这个范围，否则不能超出画布
+                let bounds = (
+                    width
+                        .filter(|&w| w > 0)
+                        .map_or(canvas.width(), |w| w.min(canvas.width())),
+                    canvas.height(),
+                );
+
+                let mask = match font {
+                    Some(font_b64) => base64::engine::general_purpose::STANDARD
+                        .decode(font_b64)
+                        .ok()
+                        .and_then(load_font)
+                        .map(|font| render_text(&font, text, *size, align, bounds)),
+                    None => {
+                        bundled_font().map(|font| render_text(font, text, *size, align, bounds))
+                    }
+                };
+                let Some(mask) = mask else {
+                    continue;
+                };
+
+                let mark = match solid {
+                    Some(color) => colorize(&mask, |_, _| color),
+                    None => colorize_text(&mask, gradient),
+                };
+                let offset = align.offset(width.unwrap_or(0) as f32, mark.width() as f32);
+                blend(
+                    &mut canvas,
+                    &mark,
+                    (*x as f32 + offset).round() as i64,
+                    *y as i64,
+                    1.0,
+                );
+            }
+            TemplateLayer::Border {
+                rect,
+                thickness,
+                color,
+                fill,
+            } => {
+                let color = parse_color(color.as_deref().unwrap_or("#000000"))?;
+                let fill = fill.as_deref().map(parse_color).transpose()?;
+                let thickness = thickness.unwrap_or(1);
+                let (w, h) = (rect.width, rect.height);
+                limits.check_working_set(w, h)?;
+
+                let border = RgbaImage::from_fn(w, h, |x, y| {
+                    let edge = x < thickness
+                        || y < thickness
+                        || x >= w.saturating_sub(thickness)
+                        || y >= h.saturating_sub(thickness);
+                    match (edge, fill) {
+                        (true, _) => color,
+                        (false, Some(fill)) => fill,
+                        (false, None) => Rgba([0, 0, 0, 0]),
+                    }
+                });
+                blend(&mut canvas, &border, rect.x as i64, rect.y as i64, 1.0);
+            }
+        }
+    }
+
+    Ok(canvas)
+}
@@ -0,0 +1,182 @@
+#[cfg(feature = "bundled-font")]
+use std::sync::OnceLock;
+
+use ab_glyph::{Font, FontRef, FontVec, PxScale, ScaleFont, point};
+use image::{GrayImage, Luma, Rgba, RgbaImage};
+
+use crate::error::OLIError;
+
+// 内置字体（DejaVu Sans Bold，只含西文字符，中文等需要通过 watermark_font 提供字体）
+#[cfg(feature = "bundled-font")]
+static BUNDLED_FONT: &[u8] = include_bytes!("../../assets/DejaVuSans-Bold.ttf");
+
+// 内置字体，第一次使用时解析并缓存；未启用 bundled-font 特性时返回 None
+pub fn bundled_font() -> Option<&'static FontRef<'static>> {
+    #[cfg(feature = "bundled-font")]
+    {
+        static PARSED: OnceLock<Option<FontRef<'static>>> = OnceLock::new();
+        PARSED
+            .get_or_init(|| FontRef::try_from_slice(BUNDLED_FONT).ok())
+            .as_ref()
+    }
+    #[cfg(not(feature = "bundled-font"))]
+    {
+        None
+    }
+}
+
+// 解析 TTF/OTF 字体数据，无法解析时返回 None
+pub fn load_font(data: Vec<u8>) -> Option<FontVec> {
+    FontVec::try_from_vec(data).ok()
+}
+
+// 多行文字的对齐方式
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+impl TextAlign {
+    pub fn from_name(name: &str) -> Result<Self, OLIError> {
+        match name {
+            "left" => Ok(TextAlign::Left),
+            "center" => Ok(TextAlign::Center),
+            "right" => Ok(TextAlign::Right),
+            _ => Err(OLIError::InvalidConfig(format!(
+                "unknown text align \"{name}\""
+            ))),
+        }
+    }
+
+    // 宽 width 的内容在宽 space 的范围内的起始偏移
+    pub fn offset(self, space: f32, width: f32) -> f32 {
+        match self {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => (space - width) / 2.0,
+            TextAlign::Right => space - width,
+        }
+    }
+}
+
+// 整段文字能放进 max_width×max_height 的最大字号（不超过 size）
+// 按排版的行宽与行高估算，不栅格化字形，可以在分配内存之前限制过大的字号
+pub fn fit_text_size<F: Font>(
+    font: &F,
+    text: &str,
+    size: f32,
+    max_width: u32,
+    max_height: u32,
+) -> f32 {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let line_height = scaled.height() + scaled.line_gap();
+    let line_count = text.lines().count().max(1);
+
+    let width = text
+        .lines()
+        .map(|line| {
+            let mut caret = 0.0;
+            let mut previous = None;
+            for c in line.chars().filter(|c| !c.is_control()) {
+                let id = scaled.glyph_id(c);
+                if let Some(previous) = previous {
+                    caret += scaled.kern(previous, id);
+                }
+                caret += scaled.h_advance(id);
+                previous = Some(id);
+            }
+            caret
+        })
+        .fold(0.0, f32::max);
+    let height = scaled.height() + (line_count - 1) as f32 * line_height;
+
+    let fit = (max_width as f32 / width)
+        .min(max_height as f32 / height)
+        .min(1.0);
+    size * fit
+}
+
+// 把文字栅格化为覆盖率蒙版（0-255），字号 size 为像素高度
+// 按换行符分行、各行按 align 对齐，图片大小裁剪到字形的实际范围；字体中没有的字符显示为缺字符号
+pub fn rasterize_text<F: Font>(font: &F, text: &str, size: f32, align: TextAlign) -> GrayImage {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let line_height = scaled.height() + scaled.line_gap();
+
+    // 先排出每一行的字形与行宽，再按最宽的一行对齐
+    let lines: Vec<(Vec<_>, f32)> = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let baseline = scaled.ascent() + i as f32 * line_height;
+            let mut glyphs = Vec::new();
+            let mut caret = 0.0;
+            let mut previous = None;
+            for c in line.chars().filter(|c| !c.is_control()) {
+                let mut glyph = scaled.scaled_glyph(c);
+                if let Some(previous) = previous {
+                    caret += scaled.kern(previous, glyph.id);
+                }
+                glyph.position = point(caret, baseline);
+                caret += scaled.h_advance(glyph.id);
+                previous = Some(glyph.id);
+                glyphs.push(glyph);
+            }
+            (glyphs, caret)
+        })
+        .collect();
+    let max_width = lines.iter().map(|(_, w)| *w).fold(0.0, f32::max);
+
+    let mut outlines = Vec::new();
+    for (glyphs, line_width) in lines {
+        let offset = align.offset(max_width, line_width);
+        for mut glyph in glyphs {
+            glyph.position.x += offset;
+            if let Some(outline) = font.outline_glyph(glyph) {
+                outlines.push(outline);
+            }
+        }
+    }
+
+    let Some(first) = outlines.first() else {
+        return GrayImage::new(0, 0);
+    };
+    let mut bounds = first.px_bounds();
+    for outline in &outlines[1..] {
+        let b = outline.px_bounds();
+        bounds.min.x = bounds.min.x.min(b.min.x);
+        bounds.min.y = bounds.min.y.min(b.min.y);
+        bounds.max.x = bounds.max.x.max(b.max.x);
+        bounds.max.y = bounds.max.y.max(b.max.y);
+    }
+    let (x0, y0) = (bounds.min.x.floor(), bounds.min.y.floor());
+    let width = (bounds.max.x.ceil() - x0) as u32;
+    let height = (bounds.max.y.ceil() - y0) as u32;
+
+    let mut mask = GrayImage::new(width, height);
+    for outline in &outlines {
+        let b = outline.px_bounds();
+        let (ox, oy) = ((b.min.x - x0) as u32, (b.min.y - y0) as u32);
+        outline.draw(|x, y, coverage| {
+            let (px, py) = (ox + x, oy + y);
+            if px < width && py < height {
+                // 相邻字形可能重叠，取较大的覆盖率
+                let v = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+                let current = mask.get_pixel(px, py)[0];
+                mask.put_pixel(px, py, Luma([current.max(v)]));
+            }
+        });
+    }
+
+    mask
+}
+
+// 给覆盖率蒙版上色：color 给出每个像素的颜色，覆盖率乘到颜色的透明度上
+pub fn colorize(mask: &GrayImage, color: impl Fn(u32, u32) -> Rgba<u8>) -> RgbaImage {
+    let (w, h) = mask.dimensions();
+    RgbaImage::from_fn(w, h, |x, y| {
+        let Rgba([r, g, b, a]) = color(x, y);
+        let coverage = mask.get_pixel(x, y)[0] as u32;
+        Rgba([r, g, b, (a as u32 * coverage / 255) as u8])
+    })
+}
@@ -0,0 +1,451 @@
+use std::borrow::Cow;
+#[cfg(feature = "bundled-watermark")]
+use std::sync::OnceLock;
+
+use image::{Rgba, RgbaImage};
+
+use crate::OLIConfig;
+use crate::error::OLIError;
+
+// 内置的官方水印：上下两行的精灵图，上半为默认样式，下半为初回样式
+#[cfg(feature = "bundled-watermark")]
+static BUNDLED_WATERMARK: &[u8] = include_bytes!("../../assets/one-last-image-logo2.png");
+
+pub const BUNDLED_WATERMARK_ROWS: u32 = 2;
+
+// 内置水印，第一次使用时解码并缓存；未启用 bundled-watermark 特性时返回 None
+pub fn bundled_watermark() -> Option<&'static RgbaImage> {
+    #[cfg(feature = "bundled-watermark")]
+    {
+        static DECODED: OnceLock<Option<RgbaImage>> = OnceLock::new();
+        DECODED
+            .get_or_init(|| {
+                image::load_from_memory_with_format(BUNDLED_WATERMARK, image::ImageFormat::Png)
+                    .ok()
+                    .map(|img| img.to_rgba8())
+            })
+            .as_ref()
+    }
+    #[cfg(not(feature = "bundled-watermark"))]
+    {
+        None
+    }
+}
+
+// 水印锚点（九宫格）
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    pub fn from_name(name: &str) -> Result<Self, OLIError> {
+        match name {
+            "top-left" => Ok(Anchor::TopLeft),
+            "top" => Ok(Anchor::Top),
+            "top-right" => Ok(Anchor::TopRight),
+            "left" => Ok(Anchor::Left),
+            "center" => Ok(Anchor::Center),
+            "right" => Ok(Anchor::Right),
+            "bottom-left" => Ok(Anchor::BottomLeft),
+            "bottom" => Ok(Anchor::Bottom),
+            "bottom-right" => Ok(Anchor::BottomRight),
+            _ => Err(OLIError::InvalidConfig(format!(
+                "unknown watermark position \"{name}\""
+            ))),
+        }
+    }
+
+    // 水平与竖直方向的对齐：0 靠左/上，1 居中，2 靠右/下
+    fn alignment(self) -> (u8, u8) {
+        match self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Top => (1, 0),
+            Anchor::TopRight => (2, 0),
+            Anchor::Left => (0, 1),
+            Anchor::Center => (1, 1),
+            Anchor::Right => (2, 1),
+            Anchor::BottomLeft => (0, 2),
+            Anchor::Bottom => (1, 2),
+            Anchor::BottomRight => (2, 2),
+        }
+    }
+}
+
+// 水印位置：锚点，或水印中心在画面中的相对坐标（0-1）
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Placement {
+    Anchor(Anchor),
+    Point(f32, f32),
+}
+
+// 水印的位置、大小、不透明度、边距与旋转
+pub struct WatermarkLayout {
+    pub placement: Placement,
+    // 相对默认大小（横图高度的15%，竖图宽度的30%）的倍数
+    pub scale: f32,
+    pub opacity: f32,
+    // 与画面边缘的距离，以水印高度为单位
+    pub margin_x: f32,
+    pub margin_y: f32,
+    // 顺时针旋转角度
+    pub rotation: f32,
+}
+
+impl WatermarkLayout {
+    pub fn from_config(config: Option<&OLIConfig>) -> Result<Self, OLIError> {
+        let x = config.and_then(|c| c.watermark_x);
+        let y = config.and_then(|c| c.watermark_y);
+        let placement = match (x, y) {
+            (Some(x), Some(y)) => Placement::Point(x, y),
+            (None, None) => Placement::Anchor(Anchor::from_name(
+                config
+                    .and_then(|c| c.watermark_position.as_deref())
+                    .unwrap_or("bottom-right"),
+            )?),
+            _ => {
+                return Err(OLIError::InvalidConfig(
+                    "watermark_x and watermark_y must be set together".to_string(),
+                ));
+            }
+        };
+
+        Ok(WatermarkLayout {
+            placement,
+            scale: config
+                .and_then(|c| c.watermark_scale)
+                .unwrap_or(1.0)
+                .max(0.0),
+            opacity: config
+                .and_then(|c| c.watermark_opacity)
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0),
+            margin_x: config.and_then(|c| c.watermark_margin_x).unwrap_or(0.2),
+            margin_y: config.and_then(|c| c.watermark_margin_y).unwrap_or(0.16),
+            rotation: config.and_then(|c| c.watermark_rotation).unwrap_or(0.0),
+        })
+    }
+}
+
+// 水印绘制方式
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WatermarkMode {
+    // 只在锚点/坐标处绘制一个
+    Single,
+    // 平铺满整个画面，同时保留单个水印
+    Tile,
+    // 只平铺
+    TileOnly,
+}
+
+impl WatermarkMode {
+    pub fn from_name(name: &str) -> Result<Self, OLIError> {
+        match name {
+            "single" => Ok(WatermarkMode::Single),
+            "tile" => Ok(WatermarkMode::Tile),
+            "tile-only" => Ok(WatermarkMode::TileOnly),
+            _ => Err(OLIError::InvalidConfig(format!(
+                "unknown watermark mode \"{name}\""
+            ))),
+        }
+    }
+
+    pub fn tiles(self) -> bool {
+        self != WatermarkMode::Single
+    }
+
+    pub fn single(self) -> bool {
+        self != WatermarkMode::TileOnly
+    }
+}
+
+// 平铺水印的方向、间距与不透明度
+pub struct TileLayout {
+    // 行方向的顺时针旋转角度，水印本身也旋转同样的角度
+    pub angle: f32,
+    // 相邻水印之间的空隙，以水印高度为单位
+    pub spacing: f32,
+    pub opacity: f32,
+}
+
+impl TileLayout {
+    pub fn from_config(config: Option<&OLIConfig>) -> Self {
+        TileLayout {
+            angle: config.and_then(|c| c.watermark_tile_angle).unwrap_or(-30.0),
+            spacing: config
+                .and_then(|c| c.watermark_tile_spacing)
+                .unwrap_or(1.0)
+                .max(0.0),
+            opacity: config
+                .and_then(|c| c.watermark_tile_opacity)
+                .unwrap_or(0.15)
+                .clamp(0.0, 1.0),
+        }
+    }
+}
+
+// rows 行 cols 列的精灵图包含的水印个数，行列数为0或乘积溢出时报错
+pub fn sprite_count(rows: u32, cols: u32) -> Result<u32, OLIError> {
+    if rows == 0 || cols == 0 {
+        return Err(OLIError::InvalidConfig(format!(
+            "watermark sprite sheet must have at least 1 row and column, got {rows}x{cols}"
+        )));
+    }
+    rows.checked_mul(cols).ok_or_else(|| {
+        OLIError::InvalidConfig(format!(
+            "watermark sprite sheet {rows}x{cols} has too many cells"
+        ))
+    })
+}
+
+// 从按 rows 行 cols 列等分的精灵图中取出第 index 个（按行优先编号，从0开始）
+pub fn sprite(sheet: &RgbaImage, rows: u32, cols: u32, index: u32) -> Result<RgbaImage, OLIError> {
+    if index >= sprite_count(rows, cols)? {
+        return Err(OLIError::InvalidConfig(format!(
+            "watermark sprite {index} is out of range for a {rows}x{cols} sheet"
+        )));
+    }
+
+    let (w, h) = sheet.dimensions();
+    let (cell_w, cell_h) = (w / cols, h / rows);
+    let (col, row) = (index % cols, index / cols);
+    Ok(image::imageops::crop_imm(sheet, col * cell_w, row * cell_h, cell_w, cell_h).to_image())
+}
+
+// 把图片水印缩放到画面对应的大小（最大为整个画面），太小时返回 None
+pub fn fit_watermark(width: u32, height: u32, mark: &RgbaImage, scale: f32) -> Option<RgbaImage> {
+    let (used_w, used_h) = mark.dimensions();
+    if used_w == 0 || used_h == 0 {
+        return None;
+    }
+
+    // 横图按高度、竖图按宽度确定水印大小
+    let (set_width, set_height) = if width as f32 / height as f32 > 1.1 {
+        let h = (height as f32 * 0.15 * scale) as u32;
+        let w = (h as f32 / used_h as f32 * used_w as f32) as u32;
+        (w, h)
+    } else {
+        let w = (width as f32 * 0.3 * scale) as u32;
+        let h = (w as f32 / used_w as f32 * used_h as f32) as u32;
+        (w, h)
+    };
+    // 不超过画面大小，过大的 scale 不会分配超出画面的缓冲区
+    let (set_width, set_height) = if set_width > width || set_height > height {
+        let fit = (width as f32 / set_width as f32).min(height as f32 / set_height as f32);
+        (
+            (set_width as f32 * fit) as u32,
+            (set_height as f32 * fit) as u32,
+        )
+    } else {
+        (set_width, set_height)
+    };
+    if set_width == 0 || set_height == 0 {
+        return None;
+    }
+
+    Some(image::imageops::resize(
+        mark,
+        set_width,
+        set_height,
+        image::imageops::FilterType::Lanczos3,
+    ))
+}
+
+// 文字水印的字号（像素）：画面短边的5%乘以大小倍数，之后再缩小到整段文字能放进画面
+pub fn text_size(width: u32, height: u32, scale: f32) -> f32 {
+    width.min(height) as f32 * 0.05 * scale
+}
+
+// 把已经是最终大小的水印旋转后按布局绘制到画面上，边距以旋转前的水印高度为单位
+pub fn place_watermark(canvas: &mut RgbaImage, mark: &RgbaImage, layout: &WatermarkLayout) {
+    let (width, height) = canvas.dimensions();
+    let mark_height = mark.height();
+    if mark.width() == 0 || mark_height == 0 {
+        return;
+    }
+
+    let rotated = if layout.rotation % 360.0 != 0.0 {
+        Cow::Owned(rotate(mark, layout.rotation))
+    } else {
+        Cow::Borrowed(mark)
+    };
+    let (mark_w, mark_h) = rotated.dimensions();
+
+    let margin_x = (mark_height as f32 * layout.margin_x) as u32;
+    let margin_y = (mark_height as f32 * layout.margin_y) as u32;
+
+    let (start_x, start_y) = match layout.placement {
+        Placement::Anchor(anchor) => {
+            let (ax, ay) = anchor.alignment();
+            let align = |align: u8, size: u32, mark: u32, margin: u32| -> i64 {
+                match align {
+                    0 => margin as i64,
+                    1 => (size as i64 - mark as i64) / 2,
+                    _ => size.saturating_sub(mark.saturating_add(margin)) as i64,
+                }
+            };
+            (
+                align(ax, width, mark_w, margin_x),
+                align(ay, height, mark_h, margin_y),
+            )
+        }
+        Placement::Point(x, y) => (
+            (x * width as f32 - mark_w as f32 / 2.0).round() as i64,
+            (y * height as f32 - mark_h as f32 / 2.0).round() as i64,
+        ),
+    };
+
+    blend(canvas, &rotated, start_x, start_y, layout.opacity);
+}
+
+// 把已经是最终大小的水印沿倾斜的网格平铺满整个画面，相邻两行错开半格
+pub fn tile_watermark(canvas: &mut RgbaImage, mark: &RgbaImage, tile: &TileLayout) {
+    let (width, height) = canvas.dimensions();
+    let (used_w, used_h) = mark.dimensions();
+    if used_w == 0 || used_h == 0 || tile.opacity <= 0.0 {
+        return;
+    }
+
+    let rotated = if tile.angle % 360.0 != 0.0 {
+        Cow::Owned(rotate(mark, tile.angle))
+    } else {
+        Cow::Borrowed(mark)
+    };
+    let (mark_w, mark_h) = rotated.dimensions();
+
+    // 网格的行方向 (cos, sin) 与列方向 (-sin, cos)，以画面中心为原点
+    let gap = tile.spacing * used_h as f32;
+    let step_u = used_w as f32 + gap;
+    let step_v = used_h as f32 + gap;
+    let (sin, cos) = tile.angle.to_radians().sin_cos();
+    let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+
+    // 覆盖画面的对角线再加一个水印的余量
+    let reach = (cx * cx + cy * cy).sqrt() + mark_w.max(mark_h) as f32;
+    let rows = (reach / step_v).ceil() as i64;
+    let cols = (reach / step_u).ceil() as i64 + 1;
+
+    for j in -rows..=rows {
+        let shift = if j.rem_euclid(2) == 1 { 0.5 } else { 0.0 };
+        for i in -cols..=cols {
+            let a = (i as f32 + shift) * step_u;
+            let b = j as f32 * step_v;
+            let x = (cx + a * cos - b * sin - mark_w as f32 / 2.0).round() as i64;
+            let y = (cy + a * sin + b * cos - mark_h as f32 / 2.0).round() as i64;
+            if x >= width as i64
+                || y >= height as i64
+                || x + (mark_w as i64) <= 0
+                || y + (mark_h as i64) <= 0
+            {
+                continue;
+            }
+            blend(canvas, &rotated, x, y, tile.opacity);
+        }
+    }
+}
+
+// 按透明度把 src 叠加到 canvas 的 (x, y) 处，超出画面的部分忽略
+pub fn blend(canvas: &mut RgbaImage, src: &RgbaImage, x: i64, y: i64, opacity: f32) {
+    let (width, height) = canvas.dimensions();
+    let (src_w, src_h) = src.dimensions();
+
+    for yy in 0..src_h {
+        for xx in 0..src_w {
+            let dst_x = x + xx as i64;
+            let dst_y = y + yy as i64;
+            if dst_x < 0 || dst_y < 0 || dst_x >= width as i64 || dst_y >= height as i64 {
+                continue;
+            }
+            let (dst_x, dst_y) = (dst_x as u32, dst_y as u32);
+
+            let src_px = src.get_pixel(xx, yy);
+            let dst_px = canvas.get_pixel(dst_x, dst_y);
+            let alpha = if opacity < 1.0 {
+                (src_px[3] as f32 * opacity).round() as u32
+            } else {
+                src_px[3] as u32
+            };
+            if alpha > 0 {
+                let inv_a = 255 - alpha;
+                let r = ((src_px[0] as u32 * alpha + dst_px[0] as u32 * inv_a) / 255) as u8;
+                let g = ((src_px[1] as u32 * alpha + dst_px[1] as u32 * inv_a) / 255) as u8;
+                let b = ((src_px[2] as u32 * alpha + dst_px[2] as u32 * inv_a) / 255) as u8;
+                canvas.put_pixel(dst_x, dst_y, Rgba([r, g, b, 255]));
+            }
+        }
+    }
+}
+
+// 绕中心顺时针旋转任意角度，画布扩大到能容纳旋转后的图片，空白处透明
+// 使用预乘透明度的双线性采样，避免透明边缘出现黑边
+pub fn rotate(img: &RgbaImage, degrees: f32) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let out_w = (w as f32 * cos.abs() + h as f32 * sin.abs()).ceil() as u32;
+    let out_h = (w as f32 * sin.abs() + h as f32 * cos.abs()).ceil() as u32;
+
+    let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+    let (ocx, ocy) = (out_w as f32 / 2.0, out_h as f32 / 2.0);
+
+    // 越界取透明
+    let fetch = |x: i64, y: i64| -> [f32; 4] {
+        if x < 0 || y < 0 || x >= w as i64 || y >= h as i64 {
+            return [0.0; 4];
+        }
+        let p = img.get_pixel(x as u32, y as u32);
+        let a = p[3] as f32 / 255.0;
+        [
+            p[0] as f32 * a,
+            p[1] as f32 * a,
+            p[2] as f32 * a,
+            p[3] as f32,
+        ]
+    };
+
+    RgbaImage::from_fn(out_w, out_h, |x, y| {
+        // 反向映射到原图坐标
+        let dx = x as f32 + 0.5 - ocx;
+        let dy = y as f32 + 0.5 - ocy;
+        let sx = dx * cos + dy * sin + cx - 0.5;
+        let sy = -dx * sin + dy * cos + cy - 0.5;
+
+        let x0 = sx.floor();
+        let y0 = sy.floor();
+        let fx = sx - x0;
+        let fy = sy - y0;
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let mut acc = [0.0f32; 4];
+        for (px, py, weight) in [
+            (x0, y0, (1.0 - fx) * (1.0 - fy)),
+            (x0 + 1, y0, fx * (1.0 - fy)),
+            (x0, y0 + 1, (1.0 - fx) * fy),
+            (x0 + 1, y0 + 1, fx * fy),
+        ] {
+            let p = fetch(px, py);
+            for c in 0..4 {
+                acc[c] += p[c] * weight;
+            }
+        }
+
+        let alpha = acc[3];
+        if alpha <= 0.0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let a = alpha / 255.0;
+        Rgba([
+            (acc[0] / a).round().clamp(0.0, 255.0) as u8,
+            (acc[1] / a).round().clamp(0.0, 255.0) as u8,
+            (acc[2] / a).round().clamp(0.0, 255.0) as u8,
+            alpha.round().clamp(0.0, 255.0) as u8,
+        ])
+    })
+}